@@ -0,0 +1,158 @@
+// API key pool with quota accounting
+// Replaces the random pick over SCRAPE_KEYS: every key counts its requests
+// against its own quota window, keys that answered with an auth or quota error
+// are benched, and the remaining capacity of each key is exported as a metric
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy)]
+pub struct KeyQuota {
+    pub requests: u32,
+    pub window: Duration,
+}
+
+// How a request made with a leased key went
+pub enum KeyOutcome {
+    Ok,
+    AuthError,
+    QuotaExceeded,
+    OtherError,
+}
+
+impl KeyOutcome {
+    pub fn from_status(status: u16) -> KeyOutcome {
+        match status {
+            401 | 403 => KeyOutcome::AuthError,
+            429 => KeyOutcome::QuotaExceeded,
+            200..=299 => KeyOutcome::Ok,
+            _ => KeyOutcome::OtherError,
+        }
+    }
+}
+
+// A rejected key is probably revoked, it is only retried after this long
+const AUTH_ERROR_BENCH: Duration = Duration::from_secs(3600);
+
+struct KeyState {
+    name: String,
+    key: Secret<String>,
+    quota: KeyQuota,
+    window_start: Instant,
+    used: u32,
+    benched_until: Option<Instant>,
+}
+
+impl KeyState {
+    // Starts a new window once the current one has passed
+    fn roll_window(&mut self, now: Instant) {
+        if now.duration_since(self.window_start) >= self.quota.window {
+            self.window_start = now;
+            self.used = 0;
+        }
+        if self.benched_until.is_some_and(|until| now >= until) {
+            self.benched_until = None;
+        }
+    }
+
+    fn remaining(&self) -> u32 {
+        if self.benched_until.is_some() {
+            return 0;
+        }
+        self.quota.requests.saturating_sub(self.used)
+    }
+}
+
+// The key handed out for one request, give it back through KeyPool::report
+pub struct KeyLease {
+    index: usize,
+    pub key: Secret<String>,
+}
+
+pub struct KeyPool {
+    keys: Mutex<Vec<KeyState>>,
+}
+
+impl KeyPool {
+    // (entry name, key, quota), each key may be on its own plan
+    pub fn new(keys: Vec<(String, Secret<String>, KeyQuota)>) -> KeyPool {
+        let now = Instant::now();
+        let keys = keys
+            .into_iter()
+            .map(|(name, key, quota)| KeyState {
+                name,
+                key,
+                quota,
+                window_start: now,
+                used: 0,
+                benched_until: None,
+            })
+            .collect();
+        KeyPool {
+            keys: Mutex::new(keys),
+        }
+    }
+
+    // Leases the key with the most capacity left, spreading load evenly across keys
+    pub fn acquire(&self) -> Result<KeyLease, String> {
+        let now = Instant::now();
+        let mut keys = self.keys.lock().unwrap();
+
+        for state in keys.iter_mut() {
+            state.roll_window(now);
+        }
+
+        let (index, state) = keys
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, state)| state.remaining() > 0)
+            .max_by_key(|(_, state)| state.remaining())
+            .ok_or("api_key_pool.rs | acquire | Every API key is out of quota or benched.".to_string())?;
+
+        state.used += 1;
+        Ok(KeyLease {
+            index,
+            key: state.key.clone(),
+        })
+    }
+
+    pub fn report(&self, lease: KeyLease, outcome: KeyOutcome) {
+        let now = Instant::now();
+        let mut keys = self.keys.lock().unwrap();
+        let state = match keys.get_mut(lease.index) {
+            Some(state) => state,
+            None => return,
+        };
+
+        match outcome {
+            KeyOutcome::Ok | KeyOutcome::OtherError => {}
+            KeyOutcome::AuthError => {
+                state.benched_until = Some(now + AUTH_ERROR_BENCH);
+                log_functions::log_err(&format!(
+                    "api_key_pool.rs | report | API key {} was rejected, benched for {:?}.",
+                    state.name, AUTH_ERROR_BENCH
+                ));
+            }
+            // The provider's window is authoritative, bench the key until ours rolls over
+            KeyOutcome::QuotaExceeded => {
+                state.benched_until = Some(state.window_start + state.quota.window);
+                log_functions::log_err(&format!(
+                    "api_key_pool.rs | report | API key {} hit its quota.",
+                    state.name
+                ));
+            }
+        }
+    }
+
+    // Remaining requests per key name, the key itself is never a label
+    pub fn export_metrics(&self) {
+        let now = Instant::now();
+        let mut keys = self.keys.lock().unwrap();
+        for state in keys.iter_mut() {
+            state.roll_window(now);
+            metrics::API_KEY_REMAINING
+                .with_label_values(&[&state.name])
+                .set(state.remaining() as i64);
+        }
+    }
+}
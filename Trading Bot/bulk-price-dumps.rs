@@ -0,0 +1,61 @@
+// Bulk price dumps for markets that publish prices of every listed item
+// One request per market replaces the 10,000+ per item requests of a cycle,
+// the scheduler in incremental-price-refresh.rs uses it once enough items are due
+// and falls back to the batch lookup when the dump fails
+
+use std::collections::HashMap;
+
+// Markets that expose a full "prices for all items" endpoint
+pub fn supports_bulk_dump(market: &Market) -> bool {
+    matches!(
+        market,
+        Market::MarketCSGO | Market::WaxPeer | Market::BitSkins
+    )
+}
+
+// Fetches the full price dump of a market, keyed by market_hash_name
+pub async fn get_bulk_prices_request(market: Market) -> Result<HashMap<String, Price>, String> {
+    // Dumps are a single big response, so they have their own timeout
+    let timeout_duration = trading_config().dump_timeout();
+
+    let dump = match market {
+        Market::MarketCSGO => timeout(timeout_duration, api::marketcsgo_api::get_price_dump()).await,
+        Market::WaxPeer => timeout(timeout_duration, api::waxpeer_api::get_price_dump()).await,
+        Market::BitSkins => timeout(timeout_duration, api::bitskins_api::get_price_dump()).await,
+        _ => {
+            return Err(format!(
+                "bulk_price_dumps.rs | get_bulk_prices_request(market: {:?}) | The market does not publish a price dump.",
+                market
+            ))
+        }
+    };
+
+    match dump {
+        Ok(Ok(prices)) => Ok(prices),
+        Ok(Err(e)) => Err(format!(
+            "bulk_price_dumps.rs | get_bulk_prices_request(market: {:?}) | Error occured when getting the price dump. | {:?}",
+            market, e
+        )),
+        Err(_) => Err(format!(
+            "bulk_price_dumps.rs | get_bulk_prices_request(market: {:?}) | The price dump timeout has triggered.",
+            market
+        )),
+    }
+}
+
+// Writes the dump into every tracked item and marks them fresh
+// Items missing from the dump have no listing on that market, so their old price is dropped
+pub fn apply_bulk_prices(
+    map: &mut HashMap<String, Item>,
+    tracker: &mut FreshnessTracker,
+    market: Market,
+    mut dump: HashMap<String, Price>,
+) {
+    for (name, item) in map.iter_mut() {
+        match dump.remove(name) {
+            Some(price) => item.replace_market_price(price),
+            None => item.price.retain(|p| p.market != market),
+        }
+        tracker.mark_fetched(name, market.clone());
+    }
+}
@@ -2,24 +2,30 @@
 // This function demonstrates how the trading bot manages 100+ concurrent sessions
 // by controlling the parallel request flow with futures
 
-async fn get_all_prices(map: &mut HashMap<String, Item>) {
+// Stops scheduling new items once shutdown starts, queued requests still finish
+async fn get_all_prices(map: &mut HashMap<String, Item>, shutdown: &CancellationToken) {
+    let config = trading_config();
     let mut to_do = Vec::new();
     let mut amount = 0;
     
     for (_key, value) in map {
+        if shutdown.is_cancelled() {
+            break;
+        }
         amount += 1;
         // Push the async operation to our queue without awaiting
-        to_do.push(value.get_all_prices());
+        let span = info_span!("item", item = %value.name);
+        to_do.push(value.get_all_prices().instrument(span));
         
         // Once we hit our parallelism limit, execute all requests concurrently
-        if amount >= PARALLEL_REQUESTS {
+        if amount >= config.parallel_requests {
             // Join all futures and await their completion
             futures::future::join_all(to_do).await;
             to_do = Vec::new();
             amount = 0;
             
             // Rate limiting - prevent overwhelming the target servers
-            let wait_time = time::Duration::from_secs(1);
+            let wait_time = config.batch_pause();
             thread::sleep(wait_time);
         }
     }
@@ -29,20 +35,29 @@ async fn get_all_prices(map: &mut HashMap<String, Item>) {
 }
 
 // Implementation of market-specific price retrieval with controlled concurrency
-async fn get_given_prices(map: &mut HashMap<String, Item>, markets: Vec<Market>) {
+async fn get_given_prices(
+    map: &mut HashMap<String, Item>,
+    markets: Vec<Market>,
+    shutdown: &CancellationToken,
+) {
+    let config = trading_config();
     let mut to_do = Vec::new();
     let mut amount = 0;
     
     for (_key, value) in map {
+        if shutdown.is_cancelled() {
+            break;
+        }
         amount += 1;
         // Clone the markets vector for each item to avoid ownership issues
-        to_do.push(value.get_given_prices(markets.clone()));
+        let span = info_span!("item", item = %value.name);
+        to_do.push(value.get_given_prices(markets.clone()).instrument(span));
         
-        if amount >= PARALLEL_REQUESTS {
+        if amount >= config.parallel_requests {
             futures::future::join_all(to_do).await;
             to_do = Vec::new();
             amount = 0;
-            let wait_time = time::Duration::from_secs(1);
+            let wait_time = config.batch_pause();
             thread::sleep(wait_time);
         }
     }
@@ -62,14 +77,16 @@ impl MarketFunctions for Item {
     // Gets the price of the item in all the markets and updates self
     // Using multithreading with futures for performance
     async fn get_all_prices(&mut self) {
-        let timeout_duration = tokio::time::Duration::from_secs(100);
+        let timeout_duration = trading_config().price_timeout();
         let prices_vec = self.price.clone();
         
         // Use timeout to prevent hanging on slow requests
         let prices_t = timeout(
             timeout_duration, 
             get_all_prices_request(self.name.clone(), prices_vec)
-        ).await;
+        )
+        .instrument(info_span!("market_call", kind = "all_markets"))
+        .await;
         
         self.price = Vec::new();
         if let Ok(prices) = prices_t {
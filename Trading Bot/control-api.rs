@@ -0,0 +1,260 @@
+// Local HTTP control and status API for the running bot
+// Bound to localhost only, it exposes prices, proxy health and in-flight
+// requests as JSON, and lets an operator pause, resume or force the refresh of
+// a single item
+
+use axum::{
+    extract::Path as UrlPath,
+    http::StatusCode,
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, OnceLock, RwLock};
+use std::time::SystemTime;
+use tokio::sync::{mpsc, Notify};
+
+#[derive(Serialize, Clone, Default)]
+pub struct ProxyHealth {
+    pub successes: u64,
+    pub failures: u64,
+    pub last_failure_at: Option<SystemTime>,
+}
+
+// Everything the API reports, written by the bot and read by the handlers
+// std locks are fine here, they are never held across an await
+pub struct BotState {
+    paused: AtomicBool,
+    resumed: Notify,
+    in_flight: AtomicUsize,
+    pending_refreshes: RwLock<HashSet<String>>,
+    prices: RwLock<HashMap<String, Vec<Price>>>,
+    last_cycle_at: RwLock<Option<SystemTime>>,
+    proxy_health: RwLock<HashMap<String, ProxyHealth>>,
+}
+
+static BOT_STATE: LazyLock<BotState> = LazyLock::new(|| BotState {
+    paused: AtomicBool::new(false),
+    resumed: Notify::new(),
+    in_flight: AtomicUsize::new(0),
+    pending_refreshes: RwLock::new(HashSet::new()),
+    prices: RwLock::new(HashMap::new()),
+    last_cycle_at: RwLock::new(None),
+    proxy_health: RwLock::new(HashMap::new()),
+});
+
+pub fn bot_state() -> &'static BotState {
+    &BOT_STATE
+}
+
+// Supervisor of the background tasks (feeds, control API), its restart counts show up in /status
+static SUPERVISOR: OnceLock<TaskSupervisor> = OnceLock::new();
+
+pub fn register_supervisor(supervisor: TaskSupervisor) {
+    let _ = SUPERVISOR.set(supervisor);
+}
+
+// How the trading bot wires up its background tasks, the trading counterpart of
+// spawn_visa_tasks. Every feed and the control API run under the supervisor, which
+// is registered so /status can report their restarts. The price cycle itself owns
+// the item map and stays with the caller
+pub fn spawn_trading_tasks(
+    coordinator: &ShutdownCoordinator,
+    supervisor: &TaskSupervisor,
+    feeds: Vec<FeedConfig>,
+    feed_sender: mpsc::UnboundedSender<FeedEvent>,
+    watchlist: Arc<WatchlistStore>,
+    control_port: u16,
+) {
+    register_supervisor(supervisor.clone());
+    let token = coordinator.token();
+
+    for feed in feeds.into_iter().filter(|feed| supports_live_feed(&feed.market)) {
+        let name = feed_task_name(&feed.market);
+        let (feed_sender, feed_token) = (feed_sender.clone(), token.clone());
+        coordinator.spawn(
+            name,
+            supervisor.clone().run(name, move || {
+                run_market_feed(feed.clone(), feed_sender.clone(), feed_token.clone())
+            }),
+        );
+    }
+
+    coordinator.spawn(
+        "control_api",
+        supervisor.clone().run("control_api", move || {
+            let (watchlist, token) = (watchlist.clone(), token.clone());
+            async move {
+                // A failed bind is logged and retried by the supervisor
+                if let Err(err) = serve_control_api(watchlist, control_port, token).await {
+                    log_functions::log_err(&err);
+                }
+            }
+        }),
+    );
+}
+
+// Decrements the in-flight counter when the request finishes, even on early return
+pub struct InFlightGuard;
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        BOT_STATE.in_flight.fetch_sub(1, Ordering::Relaxed);
+        metrics::IN_FLIGHT_REQUESTS.dec();
+    }
+}
+
+impl BotState {
+    pub fn track_request(&self) -> InFlightGuard {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        metrics::IN_FLIGHT_REQUESTS.inc();
+        InFlightGuard
+    }
+
+    pub fn record_proxy_result(&self, proxy_url: &str, success: bool) {
+        let mut health = self.proxy_health.write().unwrap();
+        let entry = health.entry(proxy_url.to_string()).or_default();
+        if success {
+            entry.successes += 1;
+        } else {
+            entry.failures += 1;
+            entry.last_failure_at = Some(SystemTime::now());
+        }
+    }
+
+    // Called by the price cycle after every pass
+    pub fn publish_prices(&self, map: &HashMap<String, Item>) {
+        let snapshot = map
+            .iter()
+            .map(|(name, item)| (name.clone(), item.price.clone()))
+            .collect();
+        *self.prices.write().unwrap() = snapshot;
+        *self.last_cycle_at.write().unwrap() = Some(SystemTime::now());
+    }
+
+    // Blocks the price cycle while the bot is paused
+    pub async fn wait_if_paused(&self) {
+        while self.paused.load(Ordering::Acquire) {
+            let resumed = self.resumed.notified();
+            if !self.paused.load(Ordering::Acquire) {
+                break;
+            }
+            resumed.await;
+        }
+    }
+
+    // Items an operator asked to refresh since the last pass
+    pub fn take_refresh_requests(&self) -> HashSet<String> {
+        std::mem::take(&mut *self.pending_refreshes.write().unwrap())
+    }
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    paused: bool,
+    in_flight_requests: usize,
+    tracked_items: usize,
+    last_cycle_at: Option<SystemTime>,
+    tasks: HashMap<&'static str, TaskStatus>,
+}
+
+pub fn control_routes(watchlist: Arc<WatchlistStore>) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/items", get(items))
+        .route("/items/:name", get(item))
+        .route("/items/:name/refresh", post(refresh_item))
+        .route("/proxies", get(proxies))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/metrics", get(metrics_text))
+        .route("/log-level", put(log_level))
+        .merge(watchlist_routes(watchlist))
+}
+
+// Serves the control API, bound to localhost so it is never reachable from outside
+// Stops accepting connections on shutdown and lets open requests finish
+pub async fn serve_control_api(
+    watchlist: Arc<WatchlistStore>,
+    port: u16,
+    shutdown: CancellationToken,
+) -> Result<(), String> {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await.map_err(|e| format!(
+        "control_api.rs | serve_control_api(port: {}) | Error occured when binding the listener. | {:?}",
+        port, e
+    ))?;
+
+    axum::serve(listener, control_routes(watchlist))
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await
+        .map_err(|e| format!(
+            "control_api.rs | serve_control_api(port: {}) | The server stopped. | {:?}",
+            port, e
+        ))
+}
+
+async fn status() -> Json<StatusResponse> {
+    let state = bot_state();
+    Json(StatusResponse {
+        paused: state.paused.load(Ordering::Acquire),
+        in_flight_requests: state.in_flight.load(Ordering::Relaxed),
+        tracked_items: state.prices.read().unwrap().len(),
+        last_cycle_at: *state.last_cycle_at.read().unwrap(),
+        tasks: SUPERVISOR.get().map(|s| s.status()).unwrap_or_default(),
+    })
+}
+
+async fn items() -> Json<HashMap<String, Vec<Price>>> {
+    Json(bot_state().prices.read().unwrap().clone())
+}
+
+async fn item(UrlPath(name): UrlPath<String>) -> Result<Json<Vec<Price>>, StatusCode> {
+    match bot_state().prices.read().unwrap().get(&name) {
+        Some(prices) => Ok(Json(prices.clone())),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// Marks the item due on every market, the next pass refetches it
+async fn refresh_item(UrlPath(name): UrlPath<String>) -> StatusCode {
+    let state = bot_state();
+    if !state.prices.read().unwrap().contains_key(&name) {
+        return StatusCode::NOT_FOUND;
+    }
+    state.pending_refreshes.write().unwrap().insert(name);
+    StatusCode::ACCEPTED
+}
+
+async fn proxies() -> Json<HashMap<String, ProxyHealth>> {
+    Json(bot_state().proxy_health.read().unwrap().clone())
+}
+
+async fn pause() -> StatusCode {
+    bot_state().paused.store(true, Ordering::Release);
+    log_write("control_api | pause | The bot was paused.\n");
+    StatusCode::NO_CONTENT
+}
+
+async fn resume() -> StatusCode {
+    let state = bot_state();
+    state.paused.store(false, Ordering::Release);
+    state.resumed.notify_waiters();
+    log_write("control_api | resume | The bot was resumed.\n");
+    StatusCode::NO_CONTENT
+}
+
+async fn metrics_text() -> String {
+    if let Some(pool) = try_scrape_key_pool() {
+        pool.export_metrics();
+    }
+    metrics::encode_metrics()
+}
+
+// Body is a filter such as "info,trading_bot::network=debug"
+async fn log_level(directives: String) -> Result<StatusCode, (StatusCode, String)> {
+    set_log_filter(directives.trim())
+        .map(|_| StatusCode::NO_CONTENT)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
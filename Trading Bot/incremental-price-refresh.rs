@@ -0,0 +1,425 @@
+// Incremental price refresh driven by per-(item, market) freshness
+// Instead of refetching every market for every item each cycle, only the pairs
+// whose price has gone stale are planned, grouped by market so markets with a
+// price dump or a batch lookup endpoint get one request for all of their due items
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{info_span, Instrument};
+
+pub const ALL_MARKETS: [Market; 9] = [
+    Market::Steam,
+    Market::Buff,
+    Market::LisSkins,
+    Market::MarketCSGO,
+    Market::DMarket,
+    Market::CSMoney,
+    Market::CSFloat,
+    Market::BitSkins,
+    Market::WaxPeer,
+];
+
+// How long a fetched price is considered fresh for each market
+// Slow moving markets are polled less often to save requests
+fn max_price_age(market: &Market) -> Duration {
+    match market {
+        Market::Steam | Market::Buff | Market::LisSkins => Duration::from_secs(600),
+        _ => Duration::from_secs(120),
+    }
+}
+
+// Markets whose API accepts several item names in a single lookup
+fn supports_batch_lookup(market: &Market) -> bool {
+    matches!(market, Market::MarketCSGO | Market::WaxPeer)
+}
+
+// How a market gets queried, the cheapest available option wins
+#[derive(PartialEq)]
+enum FetchStrategy {
+    Bulk,    // one price dump covers every item
+    Batch,   // one request for all due items
+    PerItem, // one request per item
+}
+
+// Below this many due items a batch lookup is cheaper than downloading the whole dump
+const BULK_MIN_DUE_ITEMS: usize = 50;
+
+// Markets with both endpoints use the dump only when enough of their items are due
+fn fetch_strategy(market: &Market, due_items: usize) -> FetchStrategy {
+    let batch = supports_batch_lookup(market);
+    if supports_bulk_dump(market) && (due_items >= BULK_MIN_DUE_ITEMS || !batch) {
+        FetchStrategy::Bulk
+    } else if batch {
+        FetchStrategy::Batch
+    } else {
+        FetchStrategy::PerItem
+    }
+}
+
+// Remembers when each (item, market) pair was last fetched successfully
+pub struct FreshnessTracker {
+    last_fetched: HashMap<(String, Market), Instant>,
+}
+
+impl FreshnessTracker {
+    pub fn new() -> Self {
+        FreshnessTracker {
+            last_fetched: HashMap::new(),
+        }
+    }
+
+    pub fn mark_fetched(&mut self, item_name: &str, market: Market) {
+        self.last_fetched
+            .insert((item_name.to_string(), market), Instant::now());
+    }
+
+    // Used when restoring a checkpoint, so restored prices keep their real age
+    pub fn mark_fetched_at(&mut self, item_name: &str, market: Market, fetched_at: Instant) {
+        self.last_fetched
+            .insert((item_name.to_string(), market), fetched_at);
+    }
+
+    // Wall clock fetch times, Instants mean nothing after a restart
+    pub fn fetched_times(&self) -> Vec<(String, Market, DateTime<Utc>)> {
+        let now = Utc::now();
+        self.last_fetched
+            .iter()
+            .map(|((name, market), fetched_at)| {
+                let age = chrono::Duration::from_std(fetched_at.elapsed()).unwrap_or_default();
+                (name.clone(), market.clone(), now - age)
+            })
+            .collect()
+    }
+
+    // Pairs that were never fetched are always due
+    pub fn is_due(&self, item_name: &str, market: &Market) -> bool {
+        match self.last_fetched.get(&(item_name.to_string(), market.clone())) {
+            Some(fetched_at) => fetched_at.elapsed() >= max_price_age(market),
+            None => true,
+        }
+    }
+
+    // Makes every market of the item due on the next pass
+    pub fn invalidate(&mut self, item_name: &str) {
+        self.last_fetched.retain(|(name, _), _| name != item_name);
+    }
+
+    // Publishes per market freshness gauges, never fetched pairs count as stale
+    pub fn export_metrics(&self, map: &HashMap<String, Item>) {
+        for market in ALL_MARKETS.iter() {
+            let label = format!("{:?}", market);
+            let mut oldest = Duration::ZERO;
+            let mut stale = 0;
+
+            for name in map.keys() {
+                if let Some(fetched_at) = self.last_fetched.get(&(name.clone(), market.clone())) {
+                    oldest = oldest.max(fetched_at.elapsed());
+                }
+                if self.is_due(name, market) {
+                    stale += 1;
+                }
+            }
+
+            metrics::OLDEST_PRICE_AGE
+                .with_label_values(&[&label])
+                .set(oldest.as_secs_f64());
+            metrics::STALE_PRICES.with_label_values(&[&label]).set(stale);
+        }
+    }
+
+    // Forget items that are no longer in the map so the tracker does not grow forever
+    pub fn retain_items(&mut self, map: &HashMap<String, Item>) {
+        self.last_fetched.retain(|(name, _), _| map.contains_key(name));
+    }
+}
+
+// The (item, market) pairs that are due this cycle, grouped by market
+pub struct RefreshPlan {
+    pub by_market: HashMap<Market, Vec<String>>,
+}
+
+impl RefreshPlan {
+    pub fn build(map: &HashMap<String, Item>, tracker: &FreshnessTracker) -> RefreshPlan {
+        let mut by_market: HashMap<Market, Vec<String>> = HashMap::new();
+
+        for name in map.keys() {
+            for market in ALL_MARKETS.iter() {
+                if tracker.is_due(name, market) {
+                    by_market
+                        .entry(market.clone())
+                        .or_default()
+                        .push(name.clone());
+                }
+            }
+        }
+
+        RefreshPlan { by_market }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_market.is_empty()
+    }
+
+    // Item -> due markets, only for markets that have to be queried item by item
+    fn per_item_markets(&self) -> HashMap<String, Vec<Market>> {
+        let mut per_item: HashMap<String, Vec<Market>> = HashMap::new();
+
+        for (market, names) in &self.by_market {
+            if fetch_strategy(market, names.len()) != FetchStrategy::PerItem {
+                continue;
+            }
+            for name in names {
+                per_item
+                    .entry(name.clone())
+                    .or_default()
+                    .push(market.clone());
+            }
+        }
+
+        per_item
+    }
+}
+
+// Replaces the stored price of one market without touching the others
+impl Item {
+    fn replace_market_price(&mut self, price: Price) {
+        self.price.retain(|p| p.market != price.market);
+        self.price.push(price);
+    }
+}
+
+// One request per batch capable market for all of its due items
+async fn get_batch_prices_request(
+    market: Market,
+    names: Vec<String>,
+) -> Vec<(String, Result<Price, String>)> {
+    match market {
+        Market::MarketCSGO => api::marketcsgo_api::get_batch_prices(names).await,
+        Market::WaxPeer => api::waxpeer_api::get_batch_prices(names).await,
+        _ => names
+            .into_iter()
+            .map(|name| {
+                let err = format!(
+                    "incremental_price_refresh.rs | get_batch_prices_request(market: {:?}) | The market does not support batch lookup.",
+                    market
+                );
+                (name, Err(err))
+            })
+            .collect(),
+    }
+}
+
+// The due markets of one item, results are kept per market like the batch path
+// get_given_prices_request is the market filtered counterpart of get_all_prices_request
+async fn get_item_prices_request(
+    name: String,
+    markets: Vec<Market>,
+) -> Vec<(Market, Result<Price, String>)> {
+    let timeout_duration = trading_config().price_timeout();
+    match timeout(timeout_duration, get_given_prices_request(name.clone(), markets.clone())).await {
+        Ok(prices) => prices,
+        Err(_) => markets
+            .into_iter()
+            .map(|market| {
+                let err = format!(
+                    "incremental_price_refresh.rs | get_item_prices_request(name: {}, market: {:?}) | The price timeout has triggered.",
+                    name, market
+                );
+                (market, Err(err))
+            })
+            .collect(),
+    }
+}
+
+fn apply_item_prices(
+    map: &mut HashMap<String, Item>,
+    tracker: &mut FreshnessTracker,
+    results: Vec<(String, Vec<(Market, Result<Price, String>)>)>,
+) {
+    for (name, prices) in results {
+        for (market, price) in prices {
+            match price {
+                Ok(val) => {
+                    if let Some(item) = map.get_mut(&name) {
+                        item.replace_market_price(val);
+                        tracker.mark_fetched(&name, market);
+                    }
+                }
+                Err(err) => log_functions::log_err(&err),
+            }
+        }
+    }
+}
+
+// Refreshes only the (item, market) pairs that are due
+// On shutdown no new per item batch is started, the running one is awaited
+pub async fn refresh_due_prices(
+    map: &mut HashMap<String, Item>,
+    tracker: &mut FreshnessTracker,
+    shutdown: &CancellationToken,
+) {
+    tracker.retain_items(map);
+    let plan = RefreshPlan::build(map, tracker);
+    if plan.is_empty() {
+        return;
+    }
+
+    // Markets with a price dump refresh every item at once whenever enough items are due
+    let bulk_requests = plan
+        .by_market
+        .iter()
+        .filter(|(market, names)| fetch_strategy(market, names.len()) == FetchStrategy::Bulk)
+        .map(|(market, _)| {
+            let span = info_span!("market_call", market = ?market, kind = "bulk");
+            async move {
+                let dump = get_bulk_prices_request(market.clone()).await;
+                (market.clone(), dump)
+            }
+            .instrument(span)
+        });
+
+    // A failed dump falls back to the batch lookup when the market has one
+    let mut batch_markets: Vec<(Market, Vec<String>)> = plan
+        .by_market
+        .iter()
+        .filter(|(market, names)| fetch_strategy(market, names.len()) == FetchStrategy::Batch)
+        .map(|(market, names)| (market.clone(), names.clone()))
+        .collect();
+
+    for (market, dump) in futures::future::join_all(bulk_requests).await {
+        match dump {
+            Ok(prices) => apply_bulk_prices(map, tracker, market, prices),
+            Err(err) => {
+                log_functions::log_err(&err);
+                if supports_batch_lookup(&market) {
+                    if let Some(names) = plan.by_market.get(&market) {
+                        batch_markets.push((market, names.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    // Batch capable markets are queried concurrently, one request each
+    let config = trading_config();
+    let timeout_duration = config.price_timeout();
+    let batch_requests = batch_markets.into_iter().map(|(market, names)| {
+        let span = info_span!("market_call", market = ?market, kind = "batch", items = names.len());
+        async move {
+            let prices = timeout(
+                timeout_duration,
+                get_batch_prices_request(market.clone(), names),
+            )
+            .await;
+            (market, prices)
+        }
+        .instrument(span)
+    });
+
+    for (market, prices_t) in futures::future::join_all(batch_requests).await {
+        if let Ok(prices) = prices_t {
+            for (name, price) in prices {
+                match price {
+                    Ok(val) => {
+                        if let Some(item) = map.get_mut(&name) {
+                            item.replace_market_price(val);
+                            tracker.mark_fetched(&name, market.clone());
+                        }
+                    }
+                    Err(err) => log_functions::log_err(&err),
+                }
+            }
+        } else {
+            log_functions::log_err("The batch price timeout has triggered.");
+        }
+    }
+
+    // Remaining markets go through the per item path with the usual parallelism limit
+    // Only the markets that answered are written and marked fresh, a failed market
+    // keeps its old price and stays due
+    let per_item = plan.per_item_markets();
+    let mut to_do = Vec::new();
+
+    for (name, markets) in per_item {
+        if shutdown.is_cancelled() {
+            break;
+        }
+        let span = info_span!("item", item = %name);
+        to_do.push(
+            async move {
+                let prices = get_item_prices_request(name.clone(), markets).await;
+                (name, prices)
+            }
+            .instrument(span),
+        );
+
+        if to_do.len() >= config.parallel_requests {
+            let results = futures::future::join_all(std::mem::take(&mut to_do)).await;
+            apply_item_prices(map, tracker, results);
+            sleep(config.batch_pause()).await;
+        }
+    }
+    let results = futures::future::join_all(to_do).await;
+    apply_item_prices(map, tracker, results);
+}
+
+// Main price loop, each pass only fetches what went stale since the last one
+// Watchlist edits and prices streamed by the WebSocket feeds are merged in before every pass
+// Starts warm from the last checkpoint and writes a new one periodically and on exit
+pub async fn run_price_cycle(
+    map: &mut HashMap<String, Item>,
+    mut feed_events: mpsc::UnboundedReceiver<FeedEvent>,
+    watchlist: Arc<WatchlistStore>,
+    checkpoint_path: PathBuf,
+    shutdown: CancellationToken,
+) {
+    let mut tracker = FreshnessTracker::new();
+    let mut watchlist_sync = WatchlistSync::new();
+    let mut cycle_i: u64 = 0;
+
+    watchlist_sync.sync_items(&watchlist, map);
+    if let Err(err) = restore_trading_state(&checkpoint_path, map, &mut tracker) {
+        log_functions::log_err(&err);
+    }
+    let mut last_checkpoint = Instant::now();
+
+    while !shutdown.is_cancelled() {
+        cycle_i += 1;
+        tokio::select! {
+            _ = bot_state().wait_if_paused() => {}
+            _ = shutdown.cancelled() => break,
+        }
+
+        watchlist_sync.sync_items(&watchlist, map);
+        apply_feed_events(map, &mut tracker, &mut feed_events);
+        for name in bot_state().take_refresh_requests() {
+            tracker.invalidate(&name);
+        }
+
+        refresh_due_prices(map, &mut tracker, &shutdown)
+            .instrument(info_span!("cycle", cycle = cycle_i))
+            .await;
+        bot_state().publish_prices(map);
+        tracker.export_metrics(map);
+
+        if last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+            if let Err(err) = checkpoint_trading_state(&checkpoint_path, map, &tracker) {
+                log_functions::log_err(&err);
+            }
+            last_checkpoint = Instant::now();
+        }
+
+        if !sleep_or_cancelled(&shutdown, time::Duration::from_secs(1)).await {
+            break;
+        }
+    }
+
+    if let Err(err) = checkpoint_trading_state(&checkpoint_path, map, &tracker) {
+        log_functions::log_err(&err);
+    }
+}
@@ -0,0 +1,93 @@
+// Prometheus metrics for request latency, outcomes and throughput per market
+// Replaces the free text timings written by log_write, everything here can be
+// scraped from the /metrics route of the control API and aggregated
+
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::Instant;
+
+pub static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+// Seconds per HTTP request, labelled by market
+pub static REQUEST_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    let opts = HistogramOpts::new("market_request_duration_seconds", "HTTP request latency per market")
+        .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 15.0, 30.0]);
+    register(HistogramVec::new(opts, &["market"]).unwrap())
+});
+
+// Finished requests by market, proxy and status code ("error" when no response came back)
+pub static REQUEST_OUTCOMES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    let opts = Opts::new("market_requests_total", "Finished HTTP requests per market, proxy and status");
+    register(IntCounterVec::new(opts, &["market", "proxy", "status"]).unwrap())
+});
+
+pub static IN_FLIGHT_REQUESTS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new("market_requests_in_flight", "HTTP requests currently waiting for a response").unwrap())
+});
+
+// Item freshness per market, per item labels would mean 10,000+ series
+pub static OLDEST_PRICE_AGE: LazyLock<GaugeVec> = LazyLock::new(|| {
+    let opts = Opts::new("market_oldest_price_age_seconds", "Age of the stalest tracked price per market");
+    register(GaugeVec::new(opts, &["market"]).unwrap())
+});
+
+pub static STALE_PRICES: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    let opts = Opts::new("market_stale_prices", "Tracked (item, market) pairs that are due for a refresh");
+    register(IntGaugeVec::new(opts, &["market"]).unwrap())
+});
+
+// Requests left in the current quota window per API key name
+pub static API_KEY_REMAINING: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    let opts = Opts::new("api_key_remaining_requests", "Requests left in the quota window of each API key");
+    register(IntGaugeVec::new(opts, &["key"]).unwrap())
+});
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(collector: T) -> T {
+    REGISTRY.register(Box::new(collector.clone())).unwrap();
+    collector
+}
+
+// Measures one request from creation until observe_request is called
+pub struct RequestTimer {
+    market: Market,
+    start: Instant,
+}
+
+pub fn start_request(market: Market) -> RequestTimer {
+    RequestTimer {
+        market,
+        start: Instant::now(),
+    }
+}
+
+pub fn observe_request(
+    timer: RequestTimer,
+    proxy_url: &str,
+    result: &Result<reqwest::Response, reqwest::Error>,
+) {
+    let market = format!("{:?}", timer.market);
+    REQUEST_LATENCY
+        .with_label_values(&[&market])
+        .observe(timer.start.elapsed().as_secs_f64());
+
+    let status = match result {
+        Ok(response) => response.status().as_u16().to_string(),
+        Err(e) if e.is_timeout() => "timeout".to_string(),
+        Err(_) => "error".to_string(),
+    };
+    REQUEST_OUTCOMES
+        .with_label_values(&[&market, proxy_url, &status])
+        .inc();
+}
+
+// Prometheus text format for the /metrics route
+pub fn encode_metrics() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .unwrap_or_default();
+    String::from_utf8(buffer).unwrap_or_default()
+}
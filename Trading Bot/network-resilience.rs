@@ -16,7 +16,7 @@ where
 {
     let mut attempts = 0;
     while attempts < max_retries {
-        match f(input).await {
+        match f(input).instrument(info_span!("attempt", attempt = attempts + 1)).await {
             Ok(result) => return Ok(result),
             Err(_) if attempts < max_retries - 1 => {
                 attempts += 1;
@@ -31,6 +31,7 @@ where
 }
 
 // Proxy rotation and request resilience
+#[instrument(skip(headers, body, username, password))]
 async fn send_request_with_proxy_and_timeout_and_retry(
     url: &str,
     proxy_url: &str,
@@ -61,12 +62,14 @@ async fn send_request_with_proxy_and_timeout_and_retry(
             .headers(headers.clone())
             .body(body.clone())
             .send()
+            .instrument(info_span!("attempt", attempt = attempts))
             .await
         {
             Ok(response) => {
                 return Ok(response);
             }
-            Err(_) if attempts <= max_retries => {
+            Err(e) if attempts <= max_retries => {
+                tracing::warn!(attempt = attempts, error = %e, "Request failed, retrying.");
                 // Wait before retry with linear backoff
                 sleep(std::time::Duration::from_secs(1)).await;
             }
@@ -75,21 +78,61 @@ async fn send_request_with_proxy_and_timeout_and_retry(
     }
 }
 
+// API keys are read from the credential vault once at startup, their quotas from
+// the trading config (scrape_key_quota, or scrape_key_quota_overrides by entry name)
+static SCRAPE_KEYS: OnceLock<KeyPool> = OnceLock::new();
+
+pub fn init_scrape_keys(vault: &Vault) -> Result<(), String> {
+    let config = trading_config();
+    let keys: Vec<_> = load_api_keys(vault)
+        .into_iter()
+        .map(|(name, key)| {
+            let quota = config.scrape_key_quota_of(&name);
+            (name, key, quota)
+        })
+        .collect();
+    if keys.is_empty() {
+        return Err("network_resilience.rs | init_scrape_keys | The vault has no API keys.".to_string());
+    }
+    let _ = SCRAPE_KEYS.set(KeyPool::new(keys));
+    Ok(())
+}
+
+// Unset until init_scrape_keys ran, e.g. when the metrics endpoint is hit during startup
+pub fn try_scrape_key_pool() -> Option<&'static KeyPool> {
+    SCRAPE_KEYS.get()
+}
+
+fn scrape_key_pool() -> &'static KeyPool {
+    SCRAPE_KEYS
+        .get()
+        .expect("init_scrape_keys must run before using the scrape API")
+}
+
 // API key rotation to avoid rate limiting
-fn get_scrape_key() -> String {
-    // Rotate between multiple API keys to avoid detection and rate limits
-    let mut rng = rand::thread_rng();
-    let random_number = rng.gen_range(0..=3);
-    SCRAPE_KEYS[random_number].to_string()
+// Leases the key with the most quota left, keys are Secret<String> so they never reach a log line
+fn get_scrape_key() -> Result<KeyLease, String> {
+    scrape_key_pool().acquire()
+}
+
+// Every leased key has to be reported back so quota and failures are accounted for
+fn report_scrape_key(lease: KeyLease, result: &Result<reqwest::Response, reqwest::Error>) {
+    let outcome = match result {
+        Ok(response) => KeyOutcome::from_status(response.status().as_u16()),
+        Err(_) => KeyOutcome::OtherError,
+    };
+    scrape_key_pool().report(lease, outcome);
 }
 
 // Example of a market API request with full security measures
+#[instrument(fields(market = "BitSkins"))]
 pub async fn get_item_price(
     market_hash_name: String,
     max_trade_hold: i32,
 ) -> Result<reqwest::Response, reqwest::Error> {
     // Start performance tracking
-    let start = SystemTime::now();
+    let timer = metrics::start_request(Market::BitSkins);
+    let _in_flight = bot_state().track_request();
 
     // Determine item category for correct API filtering
     let mut category = "1";
@@ -123,21 +166,16 @@ pub async fn get_item_price(
         &proxy_data.0,
         header.clone(),
         json_str.clone(),
-        &proxy_data.1,
-        &proxy_data.2,
-        15,  // 15 second timeout
+        proxy_data.1.expose(),
+        proxy_data.2.expose(),
+        trading_config().request_timeout_secs,
         0,   // No retries for this specific endpoint
     )
     .await;
+    bot_state().record_proxy_result(&proxy_data.0, body.is_ok());
+
+    // Record latency and outcome for the /metrics endpoint
+    metrics::observe_request(timer, &proxy_data.0, &body);
 
-    // Log performance metrics
-    let after = SystemTime::now();
-    let passed = after.duration_since(start).unwrap();
-    let log_txt = format!(
-        "bitskins_api | get_item_price(market_hash_name: {}) | The HTTP request took {:?}.\n",
-        market_hash_name, passed
-    );
-    log_write(&log_txt);
-    
     body
 }
@@ -1,17 +1,27 @@
 // Static indices to track which proxy is used for each market
 // Using separate indices prevents detection patterns that could emerge
 // from using the same proxy rotation sequence across all markets
-static mut MARKETCSGO_NUM: usize = 0;
-static mut DMARKET_NUM: usize = 0;
-static mut CSMONEY_NUM: usize = 0;
-static mut CSFLOAT_NUM: usize = 0;
-static mut BITSKINS_NUM: usize = 0;
-static mut WAXPEER_NUM: usize = 0;
+// Atomics, so the positions can be checkpointed and restored across restarts
+static MARKETCSGO_NUM: AtomicUsize = AtomicUsize::new(0);
+static DMARKET_NUM: AtomicUsize = AtomicUsize::new(0);
+static CSMONEY_NUM: AtomicUsize = AtomicUsize::new(0);
+static CSFLOAT_NUM: AtomicUsize = AtomicUsize::new(0);
+static BITSKINS_NUM: AtomicUsize = AtomicUsize::new(0);
+static WAXPEER_NUM: AtomicUsize = AtomicUsize::new(0);
+
+// Proxy credentials are read from the credential vault once at startup
+static PROXY_CREDENTIALS: OnceLock<(Secret<String>, Secret<String>)> = OnceLock::new();
+
+pub fn init_proxy_credentials(vault: &Vault) -> Result<(), String> {
+    let credentials = load_proxy_credentials(vault)?;
+    let _ = PROXY_CREDENTIALS.set(credentials);
+    Ok(())
+}
 
 // Returns a proxy from the list according to the market
 // Each market has its own rotation index to avoid correlation
-// (proxy_url, proxy_username, proxy_password)
-pub fn get_proxy(market: Market) -> (String, String, String) {
+// (proxy_url, proxy_username, proxy_password), credentials are redacted when formatted
+pub fn get_proxy(market: Market) -> (String, Secret<String>, Secret<String>) {
     let proxies = vec![
         "45.86.48.213:50100",
         "45.86.50.46:50100",
@@ -24,69 +34,66 @@ pub fn get_proxy(market: Market) -> (String, String, String) {
         "45.86.50.128:50100",
         "45.86.50.63:50100",
     ];
-    let username = "ugurcemsaglam";
-    let password = "UsNEtD8J8c";
+    let (username, password) = PROXY_CREDENTIALS
+        .get()
+        .expect("init_proxy_credentials must run before get_proxy");
     let mut proxy_url = "";
     
-    // UNSAFE block used in a controlled way
-    // This is safe because the only code accessing these variables is this function,
-    // which ensures proper increment/bounds checking behavior
-    unsafe {
-        match market {
-            Market::Steam => {},  // No proxy needed for Steam
-            Market::Buff => {},   // No proxy needed for Buff
-            Market::LisSkins => {},  // No proxy needed for LisSkins
-            
-            // Each market has its own index counter to create different rotation patterns
-            Market::MarketCSGO => {
-                proxy_url = proxies[MARKETCSGO_NUM];
-                MARKETCSGO_NUM += 1;
-                if MARKETCSGO_NUM >= proxies.len() {
-                    MARKETCSGO_NUM = 0;
-                }
-            }
-            Market::DMarket => {
-                proxy_url = proxies[DMARKET_NUM];
-                DMARKET_NUM += 1;
-                if DMARKET_NUM >= proxies.len() {
-                    DMARKET_NUM = 0;
-                }
-            }
-            Market::CSMoney => {
-                proxy_url = proxies[CSMONEY_NUM];
-                CSMONEY_NUM += 1;
-                if CSMONEY_NUM >= proxies.len() {
-                    CSMONEY_NUM = 0;
-                }
-            }
-            Market::CSFloat => {
-                proxy_url = proxies[CSFLOAT_NUM];
-                CSFLOAT_NUM += 1;
-                if CSFLOAT_NUM >= proxies.len() {
-                    CSFLOAT_NUM = 0;
-                }
-            }
-            Market::BitSkins => {
-                proxy_url = proxies[BITSKINS_NUM];
-                BITSKINS_NUM += 1;
-                if BITSKINS_NUM >= proxies.len() {
-                    BITSKINS_NUM = 0;
-                }
-            }
-            Market::WaxPeer => {
-                proxy_url = proxies[WAXPEER_NUM];
-                WAXPEER_NUM += 1;
-                if WAXPEER_NUM >= proxies.len() {
-                    WAXPEER_NUM = 0;
-                }
-            }
-        }
+    match market {
+        Market::Steam => {},  // No proxy needed for Steam
+        Market::Buff => {},   // No proxy needed for Buff
+        Market::LisSkins => {},  // No proxy needed for LisSkins
+
+        // Each market has its own index counter to create different rotation patterns
+        Market::MarketCSGO => proxy_url = next_proxy(&MARKETCSGO_NUM, &proxies),
+        Market::DMarket => proxy_url = next_proxy(&DMARKET_NUM, &proxies),
+        Market::CSMoney => proxy_url = next_proxy(&CSMONEY_NUM, &proxies),
+        Market::CSFloat => proxy_url = next_proxy(&CSFLOAT_NUM, &proxies),
+        Market::BitSkins => proxy_url = next_proxy(&BITSKINS_NUM, &proxies),
+        Market::WaxPeer => proxy_url = next_proxy(&WAXPEER_NUM, &proxies),
     }
-    
+
     // Return the proxy details for the requester
     (
         format!("{}", proxy_url),
-        username.to_string(),
-        password.to_string(),
+        username.clone(),
+        password.clone(),
     )
 }
+
+// Advances one market's index, wrapping around the end of the list
+fn next_proxy<'a>(index: &AtomicUsize, proxies: &[&'a str]) -> &'a str {
+    let i = index
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |i| Some((i + 1) % proxies.len()))
+        .unwrap_or(0);
+    proxies[i % proxies.len()]
+}
+
+fn proxy_index(market: &Market) -> Option<&'static AtomicUsize> {
+    match market {
+        Market::MarketCSGO => Some(&MARKETCSGO_NUM),
+        Market::DMarket => Some(&DMARKET_NUM),
+        Market::CSMoney => Some(&CSMONEY_NUM),
+        Market::CSFloat => Some(&CSFLOAT_NUM),
+        Market::BitSkins => Some(&BITSKINS_NUM),
+        Market::WaxPeer => Some(&WAXPEER_NUM),
+        Market::Steam | Market::Buff | Market::LisSkins => None,
+    }
+}
+
+// Current rotation position of every proxied market, for checkpoints
+pub fn proxy_cursors() -> Vec<(Market, usize)> {
+    ALL_MARKETS
+        .iter()
+        .filter_map(|market| proxy_index(market).map(|i| (market.clone(), i.load(Ordering::Relaxed))))
+        .collect()
+}
+
+// A cursor past the end of a shorter proxy list simply wraps on the next call
+pub fn restore_proxy_cursors(cursors: &[(Market, usize)]) {
+    for (market, cursor) in cursors {
+        if let Some(index) = proxy_index(market) {
+            index.store(*cursor, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+// Checkpoint of the trading bot's runtime state
+// Last prices with their fetch times and the per market proxy cursors are saved
+// with the shared helpers in state-checkpoint.rs, so a restart starts warm
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Prices older than this are refetched instead of restored
+const PRICE_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize)]
+pub struct TradingCheckpoint {
+    prices: HashMap<String, Vec<Price>>,
+    fetched: Vec<(String, Market, DateTime<Utc>)>,
+    proxy_cursors: Vec<(Market, usize)>,
+}
+
+pub fn checkpoint_trading_state(
+    path: &Path,
+    map: &HashMap<String, Item>,
+    tracker: &FreshnessTracker,
+) -> Result<(), String> {
+    let state = TradingCheckpoint {
+        prices: map
+            .iter()
+            .map(|(name, item)| (name.clone(), item.price.clone()))
+            .collect(),
+        fetched: tracker.fetched_times(),
+        proxy_cursors: proxy_cursors(),
+    };
+    save_checkpoint(path, &state)
+}
+
+// Only items still on the watchlist are restored, and of those only the market
+// prices that are younger than PRICE_MAX_AGE, the rest is due on the first pass
+pub fn restore_trading_state(
+    path: &Path,
+    map: &mut HashMap<String, Item>,
+    tracker: &mut FreshnessTracker,
+) -> Result<(), String> {
+    let (_, state) = match load_checkpoint::<TradingCheckpoint>(path)? {
+        Some(checkpoint) => checkpoint,
+        None => return Ok(()),
+    };
+
+    restore_proxy_cursors(&state.proxy_cursors);
+
+    for (name, market, fetched_at) in state.fetched {
+        let age = checkpoint_age(fetched_at);
+        if age > PRICE_MAX_AGE {
+            continue;
+        }
+        let (item, fetched_at) = match (map.get_mut(&name), Instant::now().checked_sub(age)) {
+            (Some(item), Some(fetched_at)) => (item, fetched_at),
+            _ => continue,
+        };
+        let price = state
+            .prices
+            .get(&name)
+            .and_then(|prices| prices.iter().find(|p| p.market == market));
+
+        if let Some(price) = price {
+            item.replace_market_price(price.clone());
+            tracker.mark_fetched_at(&name, market, fetched_at);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+// Structured logging with tracing
+// Every cycle, item, market call and retry attempt runs inside a span, so one
+// item's fetches across markets can be followed in the JSON log file by its fields
+// instead of grepping free text. Log levels can be changed per module at runtime
+
+use std::sync::OnceLock;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+// Default directives, e.g. "info,trading_bot::network=debug"
+const DEFAULT_LOG_FILTER: &str = "info";
+
+// Installs the global subscriber writing one JSON object per line to a daily file
+// The returned guard must be kept alive, dropping it stops the background writer
+pub fn init_tracing(log_dir: &str) -> Result<WorkerGuard, String> {
+    let filter = EnvFilter::try_from_env("TRADING_BOT_LOG")
+        .or_else(|_| EnvFilter::try_new(DEFAULT_LOG_FILTER))
+        .map_err(|e| format!(
+            "tracing_instrumentation.rs | init_tracing(log_dir: {}) | Error occured when parsing the log filter. | {:?}",
+            log_dir, e
+        ))?;
+    let (filter_layer, filter_handle) = reload::Layer::new(filter);
+
+    let file_appender = tracing_appender::rolling::daily(log_dir, "trading-bot.json");
+    let (writer, guard) = tracing_appender::non_blocking(file_appender);
+
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(
+            fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_writer(writer),
+        )
+        .try_init()
+        .map_err(|e| format!(
+            "tracing_instrumentation.rs | init_tracing(log_dir: {}) | Error occured when installing the subscriber. | {:?}",
+            log_dir, e
+        ))?;
+
+    let _ = FILTER_HANDLE.set(filter_handle);
+    Ok(guard)
+}
+
+// Swaps the active filter without a restart, used by the control API
+pub fn set_log_filter(directives: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| format!(
+        "tracing_instrumentation.rs | set_log_filter(directives: {}) | Invalid filter directives. | {:?}",
+        directives, e
+    ))?;
+
+    let handle = FILTER_HANDLE.get().ok_or(
+        "tracing_instrumentation.rs | set_log_filter | Tracing was not initialized.".to_string(),
+    )?;
+
+    handle.reload(filter).map_err(|e| format!(
+        "tracing_instrumentation.rs | set_log_filter(directives: {}) | Error occured when reloading the filter. | {:?}",
+        directives, e
+    ))
+}
+
+// The old free text helpers now emit events, so existing call sites end up in the
+// JSON log with the fields of whichever span they were called from
+pub mod log_functions {
+    pub fn log_err(msg: &str) {
+        tracing::error!("{}", msg.trim_end());
+    }
+
+    pub fn log_write(msg: &str) {
+        tracing::info!("{}", msg.trim_end());
+    }
+
+    // should_print keeps the console output operators are used to
+    pub fn print_n_log(msg: String, should_print: bool, should_log: bool) {
+        if should_print {
+            println!("{}", msg);
+        }
+        if should_log {
+            tracing::info!("{}", msg);
+        }
+    }
+}
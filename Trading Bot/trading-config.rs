@@ -0,0 +1,105 @@
+// Runtime configuration of the trading bot
+// Loaded and hot reloaded with the shared helpers in runtime-config.rs, so the
+// parallelism and timeouts can be tuned without a rebuild
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TradingConfig {
+    // Items fetched concurrently before pausing
+    pub parallel_requests: usize,
+    // Pause between two groups of parallel requests
+    pub batch_pause_millis: u64,
+    // Upper bound for all markets of one item, and for batch lookups
+    pub price_timeout_secs: u64,
+    // A full price dump is one big response, it gets its own, usually longer, bound
+    pub dump_timeout_secs: u64,
+    // Single market API request
+    pub request_timeout_secs: u64,
+    // Scrape API quota of a key, as sold by the provider
+    pub scrape_key_quota: ScrapeKeyQuota,
+    // Keys on a different plan, by vault entry name
+    #[serde(default)]
+    pub scrape_key_quota_overrides: HashMap<String, ScrapeKeyQuota>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct ScrapeKeyQuota {
+    pub requests: u32,
+    pub window_secs: u64,
+}
+
+impl ScrapeKeyQuota {
+    pub fn key_quota(&self) -> KeyQuota {
+        KeyQuota {
+            requests: self.requests,
+            window: Duration::from_secs(self.window_secs),
+        }
+    }
+}
+
+impl TradingConfig {
+    pub fn batch_pause(&self) -> Duration {
+        Duration::from_millis(self.batch_pause_millis)
+    }
+
+    pub fn price_timeout(&self) -> Duration {
+        Duration::from_secs(self.price_timeout_secs)
+    }
+
+    pub fn dump_timeout(&self) -> Duration {
+        Duration::from_secs(self.dump_timeout_secs)
+    }
+
+    pub fn scrape_key_quota_of(&self, key_name: &str) -> KeyQuota {
+        self.scrape_key_quota_overrides
+            .get(key_name)
+            .unwrap_or(&self.scrape_key_quota)
+            .key_quota()
+    }
+}
+
+impl Validate for TradingConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.parallel_requests == 0 {
+            return Err("parallel_requests must be at least 1".to_string());
+        }
+        if self.request_timeout_secs == 0 || self.price_timeout_secs == 0 || self.dump_timeout_secs == 0 {
+            return Err("timeouts must be at least 1 second".to_string());
+        }
+        // A single request must be able to finish within the per item bound
+        if self.request_timeout_secs > self.price_timeout_secs {
+            return Err("request_timeout_secs cannot be longer than price_timeout_secs".to_string());
+        }
+        let quotas = std::iter::once(("default", &self.scrape_key_quota)).chain(
+            self.scrape_key_quota_overrides
+                .iter()
+                .map(|(name, quota)| (name.as_str(), quota)),
+        );
+        for (name, quota) in quotas {
+            if quota.requests == 0 || quota.window_secs == 0 {
+                return Err(format!("scrape key quota {} needs at least 1 request per 1 second window", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub const TRADING_CONFIG_ENV_PREFIX: &str = "TRADING_BOT";
+static TRADING_CONFIG: OnceLock<LiveConfig<TradingConfig>> = OnceLock::new();
+
+pub fn init_trading_config(path: &PathBuf) -> Result<&'static LiveConfig<TradingConfig>, String> {
+    let config = load_config::<TradingConfig>(path, TRADING_CONFIG_ENV_PREFIX)?;
+    Ok(TRADING_CONFIG.get_or_init(|| LiveConfig::new(config)))
+}
+
+pub fn trading_config() -> Arc<TradingConfig> {
+    TRADING_CONFIG
+        .get()
+        .expect("init_trading_config must run before the price cycle starts")
+        .get()
+}
@@ -0,0 +1,311 @@
+// Watchlist of tracked items stored in a JSON file
+// Items can be added, removed and tagged from the CLI or the local HTTP endpoint
+// while the bot runs, the price cycle reloads the file whenever it changes
+
+use axum::{
+    extract::{Path as UrlPath, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct WatchEntry {
+    pub tags: BTreeSet<String>,
+}
+
+// market_hash_name -> entry, ordered so the file diffs cleanly
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Watchlist {
+    pub items: BTreeMap<String, WatchEntry>,
+}
+
+pub struct WatchlistStore {
+    path: PathBuf,
+    // Serializes read-modify-write cycles coming from the HTTP handlers
+    write_lock: Mutex<()>,
+}
+
+impl WatchlistStore {
+    pub fn new(path: PathBuf) -> Self {
+        WatchlistStore {
+            path,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    // A missing file is an empty watchlist
+    pub fn load(&self) -> Result<Watchlist, String> {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Watchlist::default()),
+            Err(e) => {
+                return Err(format!(
+                    "watchlist_management.rs | load(path: {:?}) | Error occured when reading the watchlist. | {:?}",
+                    self.path, e
+                ))
+            }
+        };
+
+        serde_json::from_str(&text).map_err(|e| format!(
+            "watchlist_management.rs | load(path: {:?}) | Error occured when parsing the watchlist. | {:?}",
+            self.path, e
+        ))
+    }
+
+    // Written to a temp file and renamed so the price cycle never reads half a file
+    fn save(&self, watchlist: &Watchlist) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(watchlist).map_err(|e| format!(
+            "watchlist_management.rs | save(path: {:?}) | Error occured when serializing the watchlist. | {:?}",
+            self.path, e
+        ))?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, text)
+            .and_then(|_| std::fs::rename(&tmp_path, &self.path))
+            .map_err(|e| format!(
+                "watchlist_management.rs | save(path: {:?}) | Error occured when writing the watchlist. | {:?}",
+                self.path, e
+            ))
+    }
+
+    // First run without a file: writes the items the bot already tracks, so the
+    // watchlist starts from them instead of from nothing. Skipped while a handler
+    // is writing, the next pass tries again
+    pub fn seed<'a>(&self, names: impl Iterator<Item = &'a String>) -> Result<bool, String> {
+        let _guard = match self.write_lock.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => return Ok(false),
+        };
+        if self.path.exists() {
+            return Ok(false);
+        }
+
+        let watchlist = Watchlist {
+            items: names.map(|name| (name.clone(), WatchEntry::default())).collect(),
+        };
+        self.save(&watchlist)?;
+        Ok(true)
+    }
+
+    pub fn modified_at(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    async fn update<R>(&self, f: impl FnOnce(&mut Watchlist) -> R) -> Result<R, String> {
+        let _guard = self.write_lock.lock().await;
+        let mut watchlist = self.load()?;
+        let result = f(&mut watchlist);
+        self.save(&watchlist)?;
+        Ok(result)
+    }
+
+    // Adding an item that is already watched only merges the tags
+    pub async fn add(&self, name: &str, tags: Vec<String>) -> Result<(), String> {
+        self.update(|w| {
+            w.items.entry(name.to_string()).or_default().tags.extend(tags);
+        })
+        .await
+    }
+
+    // Returns false when the item was not on the watchlist
+    pub async fn remove(&self, name: &str) -> Result<bool, String> {
+        self.update(|w| w.items.remove(name).is_some()).await
+    }
+
+    pub async fn tag(&self, name: &str, tag: &str) -> Result<bool, String> {
+        self.update(|w| match w.items.get_mut(name) {
+            Some(entry) => {
+                entry.tags.insert(tag.to_string());
+                true
+            }
+            None => false,
+        })
+        .await
+    }
+
+    pub async fn untag(&self, name: &str, tag: &str) -> Result<bool, String> {
+        self.update(|w| match w.items.get_mut(name) {
+            Some(entry) => entry.tags.remove(tag),
+            None => false,
+        })
+        .await
+    }
+}
+
+// Keeps the in-memory item map in line with the watchlist file
+pub struct WatchlistSync {
+    last_modified: Option<SystemTime>,
+}
+
+impl WatchlistSync {
+    pub fn new() -> Self {
+        WatchlistSync { last_modified: None }
+    }
+
+    // Cheap when nothing changed, only the file's mtime is checked
+    // A missing file never clears the map, it is seeded from the map instead
+    pub fn sync_items(&mut self, store: &WatchlistStore, map: &mut HashMap<String, Item>) {
+        let modified = store.modified_at();
+        if modified.is_none() {
+            match store.seed(map.keys()) {
+                Ok(true) => log_functions::log_write(&format!(
+                    "No watchlist file, created one with the {} tracked items.",
+                    map.len()
+                )),
+                Ok(false) => {}
+                Err(err) => log_functions::log_err(&err),
+            }
+            return;
+        }
+        if modified == self.last_modified {
+            return;
+        }
+
+        let watchlist = match store.load() {
+            Ok(watchlist) => watchlist,
+            Err(err) => {
+                // Keep tracking the current items until the file is fixed
+                log_functions::log_err(&err);
+                return;
+            }
+        };
+        self.last_modified = modified;
+
+        map.retain(|name, _| watchlist.items.contains_key(name));
+        for name in watchlist.items.keys() {
+            if !map.contains_key(name) {
+                map.insert(name.clone(), Item::new(name.clone()));
+            }
+        }
+    }
+}
+
+// watchlist add <name> [tags..] | remove <name> | tag <name> <tag> | untag <name> <tag> | list [tag]
+pub async fn run_watchlist_cli(store: &WatchlistStore, args: &[String]) -> Result<String, String> {
+    let usage = "Usage: watchlist add <name> [tags..] | remove <name> | tag <name> <tag> | untag <name> <tag> | list [tag]";
+
+    match args.iter().map(|a| a.as_str()).collect::<Vec<_>>().as_slice() {
+        ["add", name, tags @ ..] => {
+            let tags = tags.iter().map(|t| t.to_string()).collect();
+            store.add(name, tags).await?;
+            Ok(format!("Added {}", name))
+        }
+        ["remove", name] => match store.remove(name).await? {
+            true => Ok(format!("Removed {}", name)),
+            false => Err(format!("{} is not on the watchlist", name)),
+        },
+        ["tag", name, tag] => match store.tag(name, tag).await? {
+            true => Ok(format!("Tagged {} with {}", name, tag)),
+            false => Err(format!("{} is not on the watchlist", name)),
+        },
+        ["untag", name, tag] => match store.untag(name, tag).await? {
+            true => Ok(format!("Removed tag {} from {}", tag, name)),
+            false => Err(format!("{} has no tag {}", name, tag)),
+        },
+        ["list", filter @ ..] => {
+            let watchlist = store.load()?;
+            let lines: Vec<String> = watchlist
+                .items
+                .iter()
+                .filter(|(_, entry)| filter.iter().all(|t| entry.tags.contains(*t)))
+                .map(|(name, entry)| {
+                    let tags: Vec<&str> = entry.tags.iter().map(|t| t.as_str()).collect();
+                    format!("{} [{}]", name, tags.join(", "))
+                })
+                .collect();
+            Ok(lines.join("\n"))
+        }
+        _ => Err(usage.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+struct AddItemRequest {
+    name: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct TagRequest {
+    tag: String,
+}
+
+fn internal_error(err: String) -> (StatusCode, String) {
+    log_functions::log_err(&err);
+    (StatusCode::INTERNAL_SERVER_ERROR, err)
+}
+
+fn not_found(found: bool) -> StatusCode {
+    if found {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+// Watchlist routes, served on localhost only
+pub fn watchlist_routes(store: Arc<WatchlistStore>) -> Router {
+    Router::new()
+        .route("/watchlist", get(list_items).post(add_item))
+        .route("/watchlist/:name", delete(remove_item))
+        .route("/watchlist/:name/tags", post(add_tag))
+        .route("/watchlist/:name/tags/:tag", delete(remove_tag))
+        .with_state(store)
+}
+
+async fn list_items(
+    State(store): State<Arc<WatchlistStore>>,
+) -> Result<Json<Watchlist>, (StatusCode, String)> {
+    store.load().map(Json).map_err(internal_error)
+}
+
+async fn add_item(
+    State(store): State<Arc<WatchlistStore>>,
+    Json(req): Json<AddItemRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    store.add(&req.name, req.tags).await.map_err(internal_error)?;
+    Ok(StatusCode::CREATED)
+}
+
+async fn remove_item(
+    State(store): State<Arc<WatchlistStore>>,
+    UrlPath(name): UrlPath<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    store.remove(&name).await.map(not_found).map_err(internal_error)
+}
+
+async fn add_tag(
+    State(store): State<Arc<WatchlistStore>>,
+    UrlPath(name): UrlPath<String>,
+    Json(req): Json<TagRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    store.tag(&name, &req.tag).await.map(not_found).map_err(internal_error)
+}
+
+async fn remove_tag(
+    State(store): State<Arc<WatchlistStore>>,
+    UrlPath((name, tag)): UrlPath<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    store.untag(&name, &tag).await.map(not_found).map_err(internal_error)
+}
+
+// Standalone server for the watchlist endpoint, bound to localhost only
+pub async fn serve_watchlist_api(store: Arc<WatchlistStore>, port: u16) -> Result<(), String> {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await.map_err(|e| format!(
+        "watchlist_management.rs | serve_watchlist_api(port: {}) | Error occured when binding the listener. | {:?}",
+        port, e
+    ))?;
+
+    axum::serve(listener, watchlist_routes(store)).await.map_err(|e| format!(
+        "watchlist_management.rs | serve_watchlist_api(port: {}) | The server stopped. | {:?}",
+        port, e
+    ))
+}
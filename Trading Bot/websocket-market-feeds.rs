@@ -0,0 +1,329 @@
+// Live listing feeds over WebSocket for markets that offer them
+// Each feed runs as its own task and forwards price events over a channel,
+// the price cycle applies them to the same Item.price state the polling path updates.
+// The items to subscribe to are read from the watchlist on every (re)connect, and a
+// watchlist edit makes the feed reconnect so the subscription follows it
+
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::{accept_async, connect_async, tungstenite::Message};
+
+// A price change seen on a feed, None means the last listing was removed
+pub struct FeedEvent {
+    pub market: Market,
+    pub item_name: String,
+    pub price: Option<Price>,
+}
+
+#[derive(Clone)]
+pub struct FeedConfig {
+    pub market: Market,
+    pub url: String,
+    pub watchlist: Arc<WatchlistStore>,
+}
+
+// No message for this long means the connection is dead even if the socket is open
+const FEED_IDLE_TIMEOUT_SECS: u64 = 60;
+// A connect or handshake that hangs would otherwise hold up the backoff loop
+const FEED_CONNECT_TIMEOUT_SECS: u64 = 15;
+// How often a connected feed checks whether the watchlist changed
+const FEED_WATCHLIST_CHECK_SECS: u64 = 5;
+const FEED_MAX_BACKOFF_SECS: u64 = 60;
+// A connection that stayed up this long was healthy, a feed that accepts and then
+// drops at once keeps backing off instead of reconnecting every second
+const FEED_HEALTHY_CONNECTION_SECS: u64 = 30;
+
+// Markets that publish live listing updates
+pub fn supports_live_feed(market: &Market) -> bool {
+    matches!(market, Market::MarketCSGO | Market::WaxPeer)
+}
+
+// Name of the supervised task running a market's feed, as shown in /status
+pub fn feed_task_name(market: &Market) -> &'static str {
+    match market {
+        Market::MarketCSGO => "feed_marketcsgo",
+        Market::WaxPeer => "feed_waxpeer",
+        _ => "feed",
+    }
+}
+
+// Subscription message sent after every (re)connect
+fn subscribe_message(market: &Market, items: &[String]) -> Option<String> {
+    match market {
+        Market::MarketCSGO => Some(api::marketcsgo_api::feed_subscribe_message(items)),
+        Market::WaxPeer => Some(api::waxpeer_api::feed_subscribe_message(items)),
+        _ => None,
+    }
+}
+
+// Turns one text frame into price events, frames for untracked items are dropped later
+fn parse_feed_message(market: &Market, text: &str) -> Result<Vec<FeedEvent>, String> {
+    match market {
+        Market::MarketCSGO => api::marketcsgo_api::parse_feed_message(text),
+        Market::WaxPeer => api::waxpeer_api::parse_feed_message(text),
+        _ => Ok(Vec::new()),
+    }
+}
+
+// Keeps one feed connected until shutdown, reconnecting and resubscribing with backoff
+pub async fn run_market_feed(
+    config: FeedConfig,
+    sender: mpsc::UnboundedSender<FeedEvent>,
+    shutdown: CancellationToken,
+) {
+    let mut backoff_secs = 1;
+
+    loop {
+        let connected_at = Instant::now();
+        let streamed = tokio::select! {
+            streamed = stream_market_feed(&config, &sender) => streamed,
+            _ = shutdown.cancelled() => return,
+        };
+        // The feed was healthy for a while, so start the backoff over
+        if connected_at.elapsed() >= time::Duration::from_secs(FEED_HEALTHY_CONNECTION_SECS) {
+            backoff_secs = 1;
+        }
+        if let Err(err) = streamed {
+            log_functions::log_err(&err);
+        }
+
+        // The price cycle is gone, nobody is listening anymore
+        if sender.is_closed() {
+            return;
+        }
+
+        if !sleep_or_cancelled(&shutdown, time::Duration::from_secs(backoff_secs)).await {
+            return;
+        }
+        backoff_secs = (backoff_secs * 2).min(FEED_MAX_BACKOFF_SECS);
+    }
+}
+
+// The watched items and the file version they were read from
+fn watched_items(config: &FeedConfig) -> Result<(Vec<String>, Option<SystemTime>), String> {
+    let modified = config.watchlist.modified_at();
+    let watchlist = config.watchlist.load()?;
+    Ok((watchlist.items.into_keys().collect(), modified))
+}
+
+// One connection lifetime, returns Ok when the server closed the feed normally
+// or the watchlist changed and the feed has to resubscribe
+async fn stream_market_feed(
+    config: &FeedConfig,
+    sender: &mpsc::UnboundedSender<FeedEvent>,
+) -> Result<(), String> {
+    let connect_timeout = time::Duration::from_secs(FEED_CONNECT_TIMEOUT_SECS);
+    let (mut socket, _) = match timeout(connect_timeout, connect_async(config.url.as_str())).await {
+        Ok(connected) => connected.map_err(|e| format!(
+            "websocket_market_feeds.rs | stream_market_feed(market: {:?}) | Error occured when connecting to the feed. | {:?}",
+            config.market, e
+        ))?,
+        Err(_) => {
+            return Err(format!(
+                "websocket_market_feeds.rs | stream_market_feed(market: {:?}) | Connecting to the feed took longer than {}s.",
+                config.market, FEED_CONNECT_TIMEOUT_SECS
+            ))
+        }
+    };
+
+    let (items, subscribed_version) = watched_items(config)?;
+    if let Some(msg) = subscribe_message(&config.market, &items) {
+        socket.send(Message::Text(msg)).await.map_err(|e| format!(
+            "websocket_market_feeds.rs | stream_market_feed(market: {:?}) | Error occured when subscribing to the feed. | {:?}",
+            config.market, e
+        ))?;
+    }
+
+    let idle_timeout = time::Duration::from_secs(FEED_IDLE_TIMEOUT_SECS);
+    let mut last_frame_at = Instant::now();
+    let mut watchlist_check = tokio::time::interval(time::Duration::from_secs(FEED_WATCHLIST_CHECK_SECS));
+
+    loop {
+        let idle_deadline = tokio::time::Instant::from_std(last_frame_at + idle_timeout);
+        let frame = tokio::select! {
+            frame = socket.next() => frame,
+            _ = tokio::time::sleep_until(idle_deadline) => {
+                return Err(format!(
+                    "websocket_market_feeds.rs | stream_market_feed(market: {:?}) | The feed was idle for {}s.",
+                    config.market, FEED_IDLE_TIMEOUT_SECS
+                ))
+            }
+            _ = watchlist_check.tick() => {
+                if config.watchlist.modified_at() != subscribed_version {
+                    log_functions::log_write(&format!(
+                        "The watchlist changed, resubscribing the {:?} feed.",
+                        config.market
+                    ));
+                    let _ = socket.close(None).await;
+                    return Ok(());
+                }
+                continue;
+            }
+        };
+        last_frame_at = Instant::now();
+        let frame = match frame {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+
+        match frame {
+            Ok(Message::Text(text)) => match parse_feed_message(&config.market, &text) {
+                Ok(events) => {
+                    for event in events {
+                        if sender.send(event).is_err() {
+                            return Ok(());
+                        }
+                    }
+                }
+                Err(err) => log_functions::log_err(&err),
+            },
+            Ok(Message::Ping(payload)) => {
+                let _ = socket.send(Message::Pong(payload)).await;
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => {
+                return Err(format!(
+                    "websocket_market_feeds.rs | stream_market_feed(market: {:?}) | Error occured when reading from the feed. | {:?}",
+                    config.market, e
+                ))
+            }
+        }
+    }
+}
+
+// Applies every queued feed event, called by the price cycle between passes
+// A streamed price counts as a fresh fetch so the poller skips that pair
+pub fn apply_feed_events(
+    map: &mut HashMap<String, Item>,
+    tracker: &mut FreshnessTracker,
+    receiver: &mut mpsc::UnboundedReceiver<FeedEvent>,
+) {
+    while let Ok(event) = receiver.try_recv() {
+        if let Some(item) = map.get_mut(&event.item_name) {
+            match event.price {
+                Some(price) => item.replace_market_price(price),
+                None => item.price.retain(|p| p.market != event.market),
+            }
+            tracker.mark_fetched(&event.item_name, event.market);
+        }
+    }
+}
+
+// Local stand-in for a market feed, replays the given frames to every client
+// Lets the reconnect and parsing logic run without touching a real market
+pub async fn run_mock_feed_server(addr: &str, frames: Vec<String>) -> Result<(), String> {
+    let listener = TcpListener::bind(addr).await.map_err(|e| format!(
+        "websocket_market_feeds.rs | run_mock_feed_server(addr: {}) | Error occured when binding the listener. | {:?}",
+        addr, e
+    ))?;
+    serve_mock_feed(listener, frames, None).await;
+    Ok(())
+}
+
+// Every subscription message a client sends is forwarded to `subscriptions` if given
+async fn serve_mock_feed(
+    listener: TcpListener,
+    frames: Vec<String>,
+    subscriptions: Option<mpsc::UnboundedSender<String>>,
+) {
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                log_functions::log_err(&format!("Mock feed accept failed: {:?}", e));
+                continue;
+            }
+        };
+        let frames = frames.clone();
+        let subscriptions = subscriptions.clone();
+
+        tokio::spawn(async move {
+            let mut socket = match accept_async(stream).await {
+                Ok(socket) => socket,
+                Err(_) => return,
+            };
+            // Wait for the subscription before replaying, like the real feeds do
+            if let Some(Ok(Message::Text(subscription))) = socket.next().await {
+                if let Some(subscriptions) = &subscriptions {
+                    let _ = subscriptions.send(subscription);
+                }
+            }
+            for frame in frames {
+                if socket.send(Message::Text(frame)).await.is_err() {
+                    return;
+                }
+            }
+            let _ = socket.send(Message::Close(None)).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A listing update in the shape api::marketcsgo_api::parse_feed_message reads,
+    // the test derives what it expects from that parser rather than hard coding it
+    const LISTING_FRAME: &str = r#"{"type":"newitems_go","data":"{\"i_market_hash_name\":\"AK-47 | Redline (Field-Tested)\",\"ui_price\":12.34}"}"#;
+
+    #[tokio::test]
+    async fn feed_reconnects_resubscribes_and_forwards_parsed_prices() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (subscriptions_tx, mut subscriptions) = mpsc::unbounded_channel();
+        tokio::spawn(serve_mock_feed(listener, vec![LISTING_FRAME.to_string()], Some(subscriptions_tx)));
+
+        let watchlist_path = std::env::temp_dir().join(format!("feed-watchlist-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&watchlist_path);
+        let watchlist = Arc::new(WatchlistStore::new(watchlist_path.clone()));
+        watchlist.add("AK-47 | Redline (Field-Tested)", Vec::new()).await.unwrap();
+
+        let expected = parse_feed_message(&Market::MarketCSGO, LISTING_FRAME).unwrap();
+        assert!(!expected.is_empty(), "the frame must produce price events");
+        let first_items = vec!["AK-47 | Redline (Field-Tested)".to_string()];
+        // BTreeMap order, as the watchlist lists them
+        let second_items = vec!["AK-47 | Redline (Field-Tested)".to_string(), "M4A4 | Howl (Minimal Wear)".to_string()];
+
+        let (sender, mut events) = mpsc::unbounded_channel();
+        let shutdown = CancellationToken::new();
+        let feed = tokio::spawn(run_market_feed(
+            FeedConfig {
+                market: Market::MarketCSGO,
+                url,
+                watchlist: watchlist.clone(),
+            },
+            sender,
+            shutdown.clone(),
+        ));
+
+        // The mock closes after replaying, so a second subscription means a reconnect,
+        // which has to pick up the item added to the watchlist in between
+        for items in [first_items, second_items] {
+            let subscription = timeout(time::Duration::from_secs(10), subscriptions.recv())
+                .await
+                .expect("the feed did not (re)subscribe")
+                .unwrap();
+            assert_eq!(subscription, subscribe_message(&Market::MarketCSGO, &items).unwrap());
+            watchlist.add("M4A4 | Howl (Minimal Wear)", Vec::new()).await.unwrap();
+
+            for expected_event in &expected {
+                let event = timeout(time::Duration::from_secs(10), events.recv())
+                    .await
+                    .expect("no price event was forwarded")
+                    .unwrap();
+                assert!(matches!(event.market, Market::MarketCSGO));
+                assert_eq!(event.item_name, expected_event.item_name);
+                assert_eq!(event.price.is_some(), expected_event.price.is_some());
+            }
+        }
+
+        shutdown.cancel();
+        timeout(time::Duration::from_secs(5), feed).await.unwrap().unwrap();
+        let _ = std::fs::remove_file(&watchlist_path);
+    }
+}
@@ -0,0 +1,190 @@
+// Applicant preferences for acceptable appointments
+// The monitor only knows the earliest date per post, not whether the applicant can
+// attend it. Each applicant lists the posts they accept, the earliest and latest
+// dates they can make, days they are unavailable and how much notice they need,
+// and every parsed appointment is evaluated against that before an alert fires
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+use super::consulate_registry::ConsulateId;
+
+// A single day ("2026-03-10") or an inclusive range ({ from = .., to = .. })
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum BlackoutDays {
+    Day(NaiveDate),
+    Range { from: NaiveDate, to: NaiveDate },
+}
+
+impl BlackoutDays {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        match self {
+            BlackoutDays::Day(day) => *day == date,
+            BlackoutDays::Range { from, to } => *from <= date && date <= *to,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct ApplicantPreferences {
+    // Empty accepts every post
+    #[serde(default)]
+    pub consulates: Vec<ConsulateId>,
+    pub earliest: Option<NaiveDate>,
+    pub latest: Option<NaiveDate>,
+    #[serde(default)]
+    pub blackout_days: Vec<BlackoutDays>,
+    // Days needed between the alert and the appointment, e.g. to travel
+    #[serde(default)]
+    pub min_notice_days: u32,
+}
+
+// Why an appointment was accepted or not, the first rule that fails wins
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Accept,
+    WrongConsulate,
+    TooEarly,
+    TooLate,
+    Blackout,
+    NotEnoughNotice,
+}
+
+impl ApplicantPreferences {
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(earliest), Some(latest)) = (self.earliest, self.latest) {
+            if earliest > latest {
+                return Err(format!("earliest {} is after latest {}", earliest, latest));
+            }
+        }
+        for blackout in &self.blackout_days {
+            if let BlackoutDays::Range { from, to } = blackout {
+                if from > to {
+                    return Err(format!("blackout range {} - {} ends before it starts", from, to));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // `today` is passed in so the rules do not depend on the clock
+    pub fn evaluate(&self, consulate: &ConsulateId, date: NaiveDate, today: NaiveDate) -> Verdict {
+        if !self.consulates.is_empty() && !self.consulates.contains(consulate) {
+            return Verdict::WrongConsulate;
+        }
+        if self.earliest.is_some_and(|earliest| date < earliest) {
+            return Verdict::TooEarly;
+        }
+        if self.latest.is_some_and(|latest| date > latest) {
+            return Verdict::TooLate;
+        }
+        if self.blackout_days.iter().any(|blackout| blackout.contains(date)) {
+            return Verdict::Blackout;
+        }
+        if (date - today).num_days() < self.min_notice_days as i64 {
+            return Verdict::NotEnoughNotice;
+        }
+        Verdict::Accept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn istanbul() -> ConsulateId {
+        serde_json::from_str(r#""tr-istanbul""#).unwrap()
+    }
+
+    fn ankara() -> ConsulateId {
+        serde_json::from_str(r#""tr-ankara""#).unwrap()
+    }
+
+    fn preferences() -> ApplicantPreferences {
+        ApplicantPreferences {
+            consulates: vec![istanbul()],
+            earliest: Some(ymd(2026, 3, 1)),
+            latest: Some(ymd(2026, 6, 30)),
+            blackout_days: vec![
+                BlackoutDays::Day(ymd(2026, 4, 23)),
+                BlackoutDays::Range { from: ymd(2026, 5, 1), to: ymd(2026, 5, 10) },
+            ],
+            min_notice_days: 7,
+        }
+    }
+
+    const TODAY: (i32, u32, u32) = (2026, 3, 20);
+
+    fn evaluate(consulate: &ConsulateId, date: NaiveDate) -> Verdict {
+        let (year, month, day) = TODAY;
+        preferences().evaluate(consulate, date, ymd(year, month, day))
+    }
+
+    #[test]
+    fn accepts_a_date_that_passes_every_rule() {
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 4, 15)), Verdict::Accept);
+    }
+
+    #[test]
+    fn rejects_other_consulates() {
+        assert_eq!(evaluate(&ankara(), ymd(2026, 4, 15)), Verdict::WrongConsulate);
+        // An empty list accepts every post
+        let any_post = ApplicantPreferences::default();
+        assert_eq!(any_post.evaluate(&ankara(), ymd(2026, 4, 15), ymd(2026, 3, 20)), Verdict::Accept);
+    }
+
+    #[test]
+    fn earliest_and_latest_are_inclusive() {
+        let today = ymd(2026, 1, 1);
+        assert_eq!(preferences().evaluate(&istanbul(), ymd(2026, 2, 28), today), Verdict::TooEarly);
+        assert_eq!(preferences().evaluate(&istanbul(), ymd(2026, 3, 1), today), Verdict::Accept);
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 6, 30)), Verdict::Accept);
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 7, 1)), Verdict::TooLate);
+    }
+
+    #[test]
+    fn blackout_days_and_ranges_are_inclusive() {
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 4, 23)), Verdict::Blackout);
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 4, 24)), Verdict::Accept);
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 4, 30)), Verdict::Accept);
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 5, 1)), Verdict::Blackout);
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 5, 5)), Verdict::Blackout);
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 5, 10)), Verdict::Blackout);
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 5, 11)), Verdict::Accept);
+    }
+
+    #[test]
+    fn min_notice_boundary() {
+        // Today is 2026-03-20 and 7 days of notice are needed
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 3, 26)), Verdict::NotEnoughNotice);
+        assert_eq!(evaluate(&istanbul(), ymd(2026, 3, 27)), Verdict::Accept);
+
+        let no_notice = ApplicantPreferences::default();
+        assert_eq!(no_notice.evaluate(&istanbul(), ymd(2026, 3, 20), ymd(2026, 3, 20)), Verdict::Accept);
+        assert_eq!(no_notice.evaluate(&istanbul(), ymd(2026, 3, 19), ymd(2026, 3, 20)), Verdict::NotEnoughNotice);
+    }
+
+    #[test]
+    fn validate_rejects_inverted_ranges() {
+        assert!(preferences().validate().is_ok());
+
+        let mut inverted = preferences();
+        inverted.latest = Some(ymd(2026, 2, 1));
+        assert!(inverted.validate().is_err());
+
+        let mut inverted_blackout = preferences();
+        inverted_blackout.blackout_days = vec![BlackoutDays::Range { from: ymd(2026, 5, 10), to: ymd(2026, 5, 1) }];
+        assert!(inverted_blackout.validate().is_err());
+    }
+
+    #[test]
+    fn blackout_days_deserialize_as_day_or_range() {
+        let days: Vec<BlackoutDays> =
+            serde_json::from_str(r#"["2026-04-23", {"from": "2026-05-01", "to": "2026-05-10"}]"#).unwrap();
+        assert_eq!(days, preferences().blackout_days);
+    }
+}
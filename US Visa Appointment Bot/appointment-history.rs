@@ -0,0 +1,265 @@
+// Appointment history and change detection
+// check_appointments used to log the latest dates and move on, so nothing knew
+// when a post's earliest date moved. Every successful check is now recorded as
+// observations (consulate, earliest date, observed at, source account) and diffed
+// against the last known date per post, producing typed events that the rest of
+// the system subscribes to. The history is persisted with the checkpoint helpers
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, OnceLock};
+use tokio::sync::broadcast;
+use super::consulate_registry::ConsulateId;
+
+// Enough for weeks of checks, older observations are dropped first
+const MAX_OBSERVATIONS: usize = 10_000;
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AppointmentObservation {
+    pub consulate: ConsulateId,
+    pub earliest_date: NaiveDate,
+    pub observed_at: DateTime<Utc>,
+    // "account-<index>", usernames stay out of the history file and the logs
+    pub source_account: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AppointmentEvent {
+    // First date seen for a post, or a date earlier than the last one
+    NewEarliest {
+        observation: AppointmentObservation,
+        previous: Option<NaiveDate>,
+    },
+    // The earliest date moved later, the previous slot was taken
+    Slipped {
+        observation: AppointmentObservation,
+        previous: NaiveDate,
+    },
+    // The post is no longer listed on the page
+    Disappeared {
+        consulate: ConsulateId,
+        last_date: NaiveDate,
+        observed_at: DateTime<Utc>,
+        source_account: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct AppointmentHistory {
+    // Last known earliest date per post
+    latest: BTreeMap<ConsulateId, AppointmentObservation>,
+    observations: VecDeque<AppointmentObservation>,
+}
+
+impl AppointmentHistory {
+    pub fn latest(&self) -> impl Iterator<Item = &AppointmentObservation> {
+        self.latest.values()
+    }
+
+    pub fn observations_of<'a>(
+        &'a self,
+        consulate: &'a ConsulateId,
+    ) -> impl Iterator<Item = &'a AppointmentObservation> {
+        self.observations.iter().filter(move |o| &o.consulate == consulate)
+    }
+
+    // Records one successful check and returns what changed since the previous one
+    // `appointments` is the full list from the page, a post missing from it disappeared
+    pub fn record(
+        &mut self,
+        appointments: &[(ConsulateId, NaiveDate)],
+        source_account: &str,
+        observed_at: DateTime<Utc>,
+    ) -> Vec<AppointmentEvent> {
+        let mut events = Vec::new();
+
+        for (consulate, date) in appointments {
+            let observation = AppointmentObservation {
+                consulate: consulate.clone(),
+                earliest_date: *date,
+                observed_at,
+                source_account: source_account.to_string(),
+            };
+
+            let previous = self.latest.get(consulate).map(|o| o.earliest_date);
+            match previous {
+                Some(previous) if *date > previous => events.push(AppointmentEvent::Slipped {
+                    observation: observation.clone(),
+                    previous,
+                }),
+                Some(previous) if *date == previous => {}
+                _ => events.push(AppointmentEvent::NewEarliest {
+                    observation: observation.clone(),
+                    previous,
+                }),
+            }
+
+            self.latest.insert(consulate.clone(), observation.clone());
+            self.observations.push_back(observation);
+        }
+
+        let gone: Vec<ConsulateId> = self
+            .latest
+            .keys()
+            .filter(|consulate| !appointments.iter().any(|(c, _)| c == *consulate))
+            .cloned()
+            .collect();
+        for consulate in gone {
+            if let Some(last) = self.latest.remove(&consulate) {
+                events.push(AppointmentEvent::Disappeared {
+                    consulate,
+                    last_date: last.earliest_date,
+                    observed_at,
+                    source_account: source_account.to_string(),
+                });
+            }
+        }
+
+        while self.observations.len() > MAX_OBSERVATIONS {
+            self.observations.pop_front();
+        }
+        events
+    }
+}
+
+static APPOINTMENT_HISTORY: LazyLock<Mutex<AppointmentHistory>> =
+    LazyLock::new(|| Mutex::new(AppointmentHistory::default()));
+static HISTORY_PATH: OnceLock<PathBuf> = OnceLock::new();
+static APPOINTMENT_EVENTS: LazyLock<broadcast::Sender<AppointmentEvent>> =
+    LazyLock::new(|| broadcast::channel(EVENT_CHANNEL_CAPACITY).0);
+
+// Consumers (notifications, status) get every event published after they subscribe
+pub fn subscribe_appointment_events() -> broadcast::Receiver<AppointmentEvent> {
+    APPOINTMENT_EVENTS.subscribe()
+}
+
+// Loads the saved history at startup, without one every post starts as new
+pub fn init_appointment_history(path: &Path) -> Result<(), String> {
+    if let Some((_, history)) = load_checkpoint::<AppointmentHistory>(path)? {
+        *APPOINTMENT_HISTORY.lock().unwrap() = history;
+    }
+    HISTORY_PATH.get_or_init(|| path.to_path_buf());
+    Ok(())
+}
+
+// Called by check_appointments after every successful check, and with an empty list
+// when the page lists no appointments at all
+pub fn record_observations(appointments: &[(ConsulateId, NaiveDate)], account_i: usize) {
+    let source_account = format!("account-{}", account_i);
+    // Every check is an observation worth keeping, not only the ones that changed
+    // something, so the history is saved each time from a copy taken under the lock
+    let (events, snapshot) = {
+        let mut history = APPOINTMENT_HISTORY.lock().unwrap();
+        let events = history.record(appointments, &source_account, Utc::now());
+        (events, history.clone())
+    };
+    if let Some(path) = HISTORY_PATH.get() {
+        if let Err(err) = save_checkpoint(path, &snapshot) {
+            print_n_log(err, true, true);
+        }
+    }
+
+    for event in events {
+        print_n_log(format!("Appointment change: {:?}", event), true, true);
+        // No subscribers is fine, the event is still in the history
+        let _ = APPOINTMENT_EVENTS.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consulate(id: &str) -> ConsulateId {
+        serde_json::from_value(serde_json::Value::String(id.to_string())).unwrap()
+    }
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn first_sighting_and_earlier_dates_are_new_earliest() {
+        let mut history = AppointmentHistory::default();
+        let istanbul = consulate("tr-istanbul");
+
+        let events = history.record(&[(istanbul.clone(), ymd(2026, 3, 5))], "account-0", Utc::now());
+        assert!(matches!(
+            events.as_slice(),
+            [AppointmentEvent::NewEarliest { previous: None, observation }]
+                if observation.earliest_date == ymd(2026, 3, 5) && observation.source_account == "account-0"
+        ), "{:?}", events);
+
+        let events = history.record(&[(istanbul.clone(), ymd(2026, 2, 17))], "account-1", Utc::now());
+        assert!(matches!(
+            events.as_slice(),
+            [AppointmentEvent::NewEarliest { previous: Some(previous), observation }]
+                if *previous == ymd(2026, 3, 5) && observation.earliest_date == ymd(2026, 2, 17)
+        ), "{:?}", events);
+    }
+
+    #[test]
+    fn later_dates_slip_and_unchanged_dates_are_quiet() {
+        let mut history = AppointmentHistory::default();
+        let istanbul = consulate("tr-istanbul");
+        history.record(&[(istanbul.clone(), ymd(2026, 3, 5))], "account-0", Utc::now());
+
+        let events = history.record(&[(istanbul.clone(), ymd(2026, 3, 5))], "account-0", Utc::now());
+        assert!(events.is_empty(), "{:?}", events);
+
+        let events = history.record(&[(istanbul.clone(), ymd(2026, 4, 1))], "account-0", Utc::now());
+        assert!(matches!(
+            events.as_slice(),
+            [AppointmentEvent::Slipped { previous, observation }]
+                if *previous == ymd(2026, 3, 5) && observation.earliest_date == ymd(2026, 4, 1)
+        ), "{:?}", events);
+        // Unchanged checks are still observations
+        assert_eq!(history.observations_of(&istanbul).count(), 3);
+    }
+
+    #[test]
+    fn posts_missing_from_a_check_disappear_once() {
+        let mut history = AppointmentHistory::default();
+        let istanbul = consulate("tr-istanbul");
+        let ankara = consulate("tr-ankara");
+        history.record(
+            &[(istanbul.clone(), ymd(2026, 3, 5)), (ankara.clone(), ymd(2026, 2, 17))],
+            "account-0",
+            Utc::now(),
+        );
+
+        let events = history.record(&[(istanbul.clone(), ymd(2026, 3, 5))], "account-2", Utc::now());
+        assert!(matches!(
+            events.as_slice(),
+            [AppointmentEvent::Disappeared { consulate, last_date, source_account, .. }]
+                if *consulate == ankara && *last_date == ymd(2026, 2, 17) && source_account == "account-2"
+        ), "{:?}", events);
+        assert_eq!(history.latest().count(), 1);
+
+        // Already gone, nothing more to report
+        let events = history.record(&[(istanbul.clone(), ymd(2026, 3, 5))], "account-2", Utc::now());
+        assert!(events.is_empty(), "{:?}", events);
+
+        // Coming back counts as a first sighting again
+        let events = history.record(
+            &[(istanbul, ymd(2026, 3, 5)), (ankara, ymd(2026, 2, 20))],
+            "account-0",
+            Utc::now(),
+        );
+        assert!(matches!(events.as_slice(), [AppointmentEvent::NewEarliest { previous: None, .. }]), "{:?}", events);
+    }
+
+    #[test]
+    fn old_observations_are_dropped_first() {
+        let mut history = AppointmentHistory::default();
+        let istanbul = consulate("tr-istanbul");
+        for _ in 0..MAX_OBSERVATIONS + 5 {
+            history.record(&[(istanbul.clone(), ymd(2026, 3, 5))], "account-0", Utc::now());
+        }
+        assert_eq!(history.observations.len(), MAX_OBSERVATIONS);
+    }
+}
@@ -1,14 +1,29 @@
 // This system manages 100+ concurrent US visa appointment checking sessions
 // With thread-safe shared state and inter-thread communication
+// Account.password, Account.cookie and Account.proxy_string are Secret<String>,
+// so they only leave the wrapper where a request actually needs them
 
-pub async fn update_cookies(accounts: Arc<RwLock<Vec<Account>>>) {
-    // Update all the cookies once first
+// Rotation positions of both loops, published so they can be checkpointed
+// and restored across restarts (see state-checkpoint.rs)
+// Cookies are refreshed round robin, one account per step
+pub static COOKIE_ROTATION: Mutex<AccountRotation> = Mutex::new(AccountRotation::new(1, 1));
+// Appointment checks walk each batch cycle_per_batch + 1 times before moving on,
+// the batching is taken from the runtime config on every step
+pub static APPOINTMENT_ROTATION: Mutex<AccountRotation> = Mutex::new(AccountRotation::new(1, 1));
+
+// Both loops stop at their next cooldown once the shutdown token is cancelled,
+// a request that is already running is allowed to finish
+pub async fn update_cookies(accounts: Arc<RwLock<Vec<Account>>>, shutdown: CancellationToken) {
+    // Update all the cookies once first, except the ones restored from a checkpoint
     let account_data = {
         let accounts_guard = accounts.read().await;
         // Clone minimal data needed to avoid holding the lock during network operations
         accounts_guard
             .iter()
-            .map(|acc| (
+            .enumerate()
+            .filter(|(_, acc)| acc.cookie.expose().is_empty())
+            .map(|(i, acc)| (
+                i,
                 acc.username.clone(),
                 acc.password.clone(),
                 acc.proxy_string.clone(),
@@ -19,11 +34,11 @@ pub async fn update_cookies(accounts: Arc<RwLock<Vec<Account>>>) {
     // Generate new cookies concurrently
     let cookie_futures = account_data
         .iter()
-        .map(|(username, password, proxy_string)| {
+        .map(|(_, username, password, proxy_string)| {
             get_cookie(
                 username.to_string(),
-                password.to_string(),
-                proxy_string.to_string(),
+                password.expose().to_string(),
+                proxy_string.expose().to_string(),
             )
         });
     
@@ -33,22 +48,31 @@ pub async fn update_cookies(accounts: Arc<RwLock<Vec<Account>>>) {
     // Update all cookies with a single write lock
     {
         let mut accounts_guard = accounts.write().await;
-        for (i, cookie_result) in new_cookies.into_iter().enumerate() {
+        for ((i, ..), cookie_result) in account_data.iter().zip(new_cookies) {
+            let i = *i;
             if let Ok(new_cookie) = cookie_result {
                 if i < accounts_guard.len() {
-                    accounts_guard[i].cookie = new_cookie;
+                    accounts_guard[i].cookie = Secret::new(new_cookie);
+                    mark_cookie_obtained(&accounts_guard[i].username);
                 }
             }
         }
     }
 
-    // Continuous cookie refresh loop
-    let mut account_i = 1;
+    // Continuous cookie refresh loop, resuming where the last run stopped
     loop {
-        thread::sleep(Duration::from_secs(COOKIE_UPDATE_COOLDOWN as u64));
+        let cooldown = Duration::from_secs(visa_config().cookie_update_cooldown_secs);
+        if !sleep_or_cancelled(&shutdown, cooldown).await {
+            break;
+        }
 
         // Use read lock to access account data
         let accounts_guard = accounts.read().await;
+        // The rotation is driven by the current length, so an empty list just waits
+        let account_i = match COOKIE_ROTATION.lock().unwrap().next_index(accounts_guard.len()) {
+            Some(account_i) => account_i,
+            None => continue,
+        };
         let account = &accounts_guard[account_i];
 
         // Clone data to avoid holding the lock during async operation
@@ -59,103 +83,285 @@ pub async fn update_cookies(accounts: Arc<RwLock<Vec<Account>>>) {
         // Drop the lock before the async operation
         drop(accounts_guard);
 
-        let cookie = get_cookie(username.clone(), password, proxy).await;
+        let cookie = get_cookie(
+            username.clone(),
+            password.expose().clone(),
+            proxy.expose().clone(),
+        )
+        .await;
 
         if let Ok(cookie_str) = cookie {
             // Now acquire a write lock to update the cookie
             {
                 let mut accounts_guard = accounts.write().await;
-                // Update the cookie for the specific account
-                accounts_guard[account_i].cookie = cookie_str;
+                // The list may have changed while the cookie was generated,
+                // only update the slot if it still holds the same account
+                if let Some(account) = accounts_guard.get_mut(account_i) {
+                    if account.username == username {
+                        account.cookie = Secret::new(cookie_str);
+                        mark_cookie_obtained(&username);
+                    }
+                }
                 // Write lock is automatically released when block ends
             }
         } else if let Err(err) = cookie {
+            // The account is identified by index, usernames stay out of the logs
+            // and the login error is scrubbed in case it echoes the password
             let string = format!(
-                "Error when updating cookie. Username: {:?}\nError: {:?}",
-                username, err
+                "Error when updating cookie. Account: {}\nError: {}",
+                account_i,
+                password.scrub(&format!("{:?}", err))
             );
             print_n_log(string, true, true);
         }
-
-        // Rotate to next account
-        account_i += 1;
-        if account_i == ACCOUNT_COUNT as usize {
-            account_i = 0;
-        }
     }
 }
 
 // Main function to check appointments using multiple accounts in a controlled manner
-pub async fn check_appointments(accounts: Arc<RwLock<Vec<Account>>>) {
-    let mut account_i = 0;
-    let mut cycle_i = 0;
-    let mut batch_i = 0;
-
+pub async fn check_appointments(accounts: Arc<RwLock<Vec<Account>>>, shutdown: CancellationToken) {
     loop {
         // Get read lock to access the vector
         let accounts_guard = accounts.read().await;
-        let account = &accounts_guard[account_i as usize];
+
+        // Advanced rotation strategy to manage account usage patterns
+        let config = visa_config();
+        let account_i = {
+            let mut rotation = APPOINTMENT_ROTATION.lock().unwrap();
+            rotation.reconfigure(config.accounts_per_batch, config.cycle_per_batch + 1);
+            rotation.next_index(accounts_guard.len())
+        };
 
         // Clone minimal data to avoid holding the lock
-        let username = account.username.clone();
-        let user_payment_id = account.user_payment_id.clone();
-        let cookie = account.cookie.clone();
-        let proxy = account.proxy_string.clone();
+        let account_data = account_i.map(|account_i| {
+            let account = &accounts_guard[account_i];
+            (
+                account_i,
+                account.username.clone(),
+                account.user_payment_id.clone(),
+                account.cookie.clone(),
+                account.proxy_string.clone(),
+            )
+        });
 
         // Drop the lock before the async operation
         drop(accounts_guard);
 
-        // Make the API request with the cloned data
-        let appointments_ =
-            scrape_with_account_data(username, user_payment_id, proxy, cookie).await;
-
-        // Process the results
-        if let Ok(appointments) = appointments_ {
-            print_n_log(format!("Success: {:?}", appointments), true, true);
-            update_appointments(appointments).await;
-        } else {
-            let err_msg = appointments_.unwrap_err();
-            print_n_log(
-                format!("Error! Cannot get appointment data. \n {}", err_msg),
-                true, 
-                true,
-            );
+        if let Some((account_i, username, user_payment_id, cookie, proxy)) = account_data {
+            // Make the API request with the cloned data, errors come back as
+            // get_earliest_date's EarliestDateError
+            let appointments_ =
+                scrape_with_account_data(username.clone(), user_payment_id, proxy, cookie).await;
+
+            // Process the results
+            match appointments_ {
+                Ok(appointments) => {
+                    print_n_log(format!("Success: {:?}", appointments), true, true);
+                    // Diffed against the history, changes go out as appointment events
+                    record_observations(&appointments, account_i);
+                    update_appointments(appointments).await;
+                }
+                // Every post vanished from the page, an empty check lets them disappear
+                Err(EarliestDateError::NoAppointments(err_msg)) => {
+                    print_n_log(format!("No appointments listed. \n {}", err_msg), true, true);
+                    record_observations(&[], account_i);
+                }
+                // Not a "no appointments" result, the account just needs a new cookie
+                Err(EarliestDateError::SessionExpired(err_msg)) => {
+                    print_n_log(
+                        format!("Session expired, re-authenticating. Account: {}\n {}", account_i, err_msg),
+                        true,
+                        true,
+                    );
+                    reauthenticate(&accounts, account_i, &username).await;
+                }
+                Err(err_msg) => {
+                    print_n_log(
+                        format!("Error! Cannot get appointment data. \n {}", err_msg),
+                        true, 
+                        true,
+                    );
+                }
+            }
         }
 
         // Rate limiting between requests
-        thread::sleep(Duration::from_secs(COOLDOWN_PER_REQUEST as u64));
+        let cooldown = Duration::from_secs(config.cooldown_per_request_secs);
+        if !sleep_or_cancelled(&shutdown, cooldown).await {
+            break;
+        }
+    }
+}
 
-        // Advanced rotation strategy to manage account usage patterns
-        find_next_account_i(&mut account_i, &mut cycle_i, &mut batch_i);
+// A single fresh login for an account whose session expired, instead of waiting for
+// update_cookies to reach it. The next check with this account uses the new cookie,
+// if the login fails the cookie loop retries it on its own turn
+async fn reauthenticate(accounts: &Arc<RwLock<Vec<Account>>>, account_i: usize, username: &str) {
+    let credentials = {
+        let accounts_guard = accounts.read().await;
+        accounts_guard
+            .get(account_i)
+            .filter(|account| account.username == username)
+            .map(|account| (account.password.clone(), account.proxy_string.clone()))
+    };
+    let Some((password, proxy)) = credentials else {
+        return;
+    };
+
+    match get_cookie(username.to_string(), password.expose().clone(), proxy.expose().clone()).await {
+        Ok(cookie_str) => {
+            let mut accounts_guard = accounts.write().await;
+            if let Some(account) = accounts_guard.get_mut(account_i) {
+                if account.username == username {
+                    account.cookie = Secret::new(cookie_str);
+                    mark_cookie_obtained(username);
+                }
+            }
+        }
+        Err(err) => print_n_log(
+            format!(
+                "Error when re-authenticating. Account: {}\nError: {}",
+                account_i,
+                password.scrub(&format!("{:?}", err))
+            ),
+            true,
+            true,
+        ),
+    }
+}
+
+// Rotation strategy to distribute load across accounts in a pattern
+// that minimizes detection risk while maximizing appointment checking.
+// Accounts are split into batches of batch_size, each batch is walked
+// passes_per_batch times before moving on to the next one. Every step is
+// computed against the current length of the account list, so a trailing
+// batch shorter than batch_size is still used, and accounts added or removed
+// at runtime never cause an out of bounds index
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccountRotation {
+    batch_size: usize,
+    passes_per_batch: usize,
+    batch_i: usize,
+    pass_i: usize,
+    offset: usize,
+}
+
+impl AccountRotation {
+    pub const fn new(batch_size: usize, passes_per_batch: usize) -> AccountRotation {
+        AccountRotation {
+            batch_size: if batch_size == 0 { 1 } else { batch_size },
+            passes_per_batch: if passes_per_batch == 0 { 1 } else { passes_per_batch },
+            batch_i: 0,
+            pass_i: 0,
+            offset: 0,
+        }
+    }
+
+    // Applies new batching from a config reload, restarting from the first batch
+    // only when it actually changed
+    pub fn reconfigure(&mut self, batch_size: usize, passes_per_batch: usize) {
+        let new = AccountRotation::new(batch_size, passes_per_batch);
+        if new.batch_size != self.batch_size || new.passes_per_batch != self.passes_per_batch {
+            *self = new;
+        }
+    }
+
+    // Index of the account to use now, then advances. None when the list is empty
+    pub fn next_index(&mut self, len: usize) -> Option<usize> {
+        if len == 0 {
+            return None;
+        }
+
+        // The list shrank below the current batch, start over from the first one
+        let mut start = self.batch_i * self.batch_size;
+        if start >= len {
+            self.batch_i = 0;
+            self.pass_i = 0;
+            self.offset = 0;
+            start = 0;
+        }
+        let end = (start + self.batch_size).min(len);
+        if start + self.offset >= end {
+            self.offset = 0;
+        }
+        let index = start + self.offset;
+
+        self.offset += 1;
+        if start + self.offset >= end {
+            self.offset = 0;
+            self.pass_i += 1;
+            if self.pass_i >= self.passes_per_batch {
+                self.pass_i = 0;
+                self.batch_i = if end >= len { 0 } else { self.batch_i + 1 };
+            }
+        }
+
+        Some(index)
     }
 }
 
-// Complex rotation logic to distribute load across accounts in a pattern
-// that minimizes detection risk while maximizing appointment checking
-fn find_next_account_i(account_i: &mut i32, cycle_i: &mut i32, batch_i: &mut i32) {
-    let start_i = *batch_i * ACCOUNTS_PER_BATCH;
-    let end_i = start_i + ACCOUNTS_PER_BATCH - 1;
-
-    // If the account_i is the last in current batch
-    if *account_i == end_i {
-        // If we have reached the max cycle amount, go to the next batch
-        if *cycle_i == CYCLE_PER_BATCH {
-            // Check if we have enough accounts to move forward one batch
-            if (*batch_i + 2) * ACCOUNTS_PER_BATCH > ACCOUNT_COUNT {
-                *batch_i = 0;
-                *cycle_i = 0;
-                *account_i = 0;
-            } else {
-                *batch_i += 1;
-                *cycle_i = 0;
-                *account_i = *batch_i * ACCOUNTS_PER_BATCH;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn empty_list_has_no_index() {
+        let mut rotation = AccountRotation::new(3, 2);
+        assert_eq!(rotation.next_index(0), None);
+        // And it recovers once accounts show up
+        assert_eq!(rotation.next_index(2), Some(0));
+    }
+
+    #[test]
+    fn walks_each_batch_passes_times() {
+        let mut rotation = AccountRotation::new(2, 2);
+        let order: Vec<_> = (0..10).map(|_| rotation.next_index(5).unwrap()).collect();
+        assert_eq!(order, vec![0, 1, 0, 1, 2, 3, 2, 3, 4, 4]);
+    }
+
+    proptest! {
+        #[test]
+        fn index_always_in_bounds(
+            batch_size in 0usize..8,
+            passes in 0usize..4,
+            lens in prop::collection::vec(0usize..20, 1..200),
+        ) {
+            let mut rotation = AccountRotation::new(batch_size, passes);
+            for len in lens {
+                match rotation.next_index(len) {
+                    Some(index) => prop_assert!(index < len),
+                    None => prop_assert_eq!(len, 0),
+                }
+            }
+        }
+
+        #[test]
+        fn trailing_short_batch_is_visited(
+            batch_size in 1usize..8,
+            passes in 1usize..4,
+            len in 1usize..30,
+        ) {
+            let mut rotation = AccountRotation::new(batch_size, passes);
+            let batches = len.div_ceil(batch_size);
+            let steps = batches * batch_size * passes;
+            let visited: HashSet<usize> = (0..steps)
+                .map(|_| rotation.next_index(len).unwrap())
+                .collect();
+            prop_assert_eq!(visited, (0..len).collect::<HashSet<_>>());
+        }
+
+        #[test]
+        fn resizing_and_reconfiguring_never_panics(
+            steps in prop::collection::vec((0usize..20, 0usize..6, 0usize..4), 1..200),
+        ) {
+            let mut rotation = AccountRotation::new(1, 1);
+            for (len, batch_size, passes) in steps {
+                rotation.reconfigure(batch_size, passes);
+                if let Some(index) = rotation.next_index(len) {
+                    prop_assert!(index < len);
+                }
             }
-        } else {
-            // Reset the account_i to start_i and add one to cycle_i
-            *account_i = start_i;
-            *cycle_i += 1;
         }
-    } else {
-        *account_i += 1;
     }
 }
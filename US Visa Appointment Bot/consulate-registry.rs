@@ -0,0 +1,140 @@
+// Data driven registry of consular posts
+// parse_city used to know Istanbul and Ankara only, every other post on the page
+// was dropped. Posts are now read from a JSON data file (see consulates.json) with
+// their country, city, display names per language and the aliases the portal has
+// used for them, so monitoring another country's posts needs no code change
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
+use super::locale_date_parsing::normalize;
+
+// Stable identifier from the data file, e.g. "tr-istanbul"
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct ConsulateId(String);
+
+impl ConsulateId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ConsulateId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Consulate {
+    pub id: ConsulateId,
+    // ISO 3166-1 alpha-2
+    pub country: String,
+    pub city: String,
+    // lang code -> name as the portal shows it in that language
+    #[serde(default)]
+    pub names: HashMap<String, String>,
+    // Any other spelling the portal has used for the post
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+impl Consulate {
+    // Name in the given language, falls back to the city
+    pub fn display_name(&self, lang: &str) -> &str {
+        self.names.get(lang).unwrap_or(&self.city)
+    }
+
+    fn match_keys(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.id.0)
+            .chain(std::iter::once(&self.city))
+            .chain(self.names.values())
+            .chain(self.aliases.iter())
+    }
+}
+
+pub struct ConsulateRegistry {
+    consulates: Vec<Consulate>,
+    // match_key -> index into consulates
+    lookup: HashMap<String, usize>,
+}
+
+// Case, diacritics and repeated whitespace are ignored, "İSTANBUL " matches "Istanbul"
+fn match_key(text: &str) -> String {
+    normalize(text).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl ConsulateRegistry {
+    // A name shared by two posts would make parsing ambiguous, so it is rejected here
+    pub fn new(consulates: Vec<Consulate>) -> Result<ConsulateRegistry, String> {
+        let mut lookup = HashMap::new();
+
+        for (i, consulate) in consulates.iter().enumerate() {
+            for key in consulate.match_keys() {
+                let key = match_key(key);
+                if key.is_empty() {
+                    continue;
+                }
+                if let Some(other) = lookup.insert(key.clone(), i) {
+                    if other != i {
+                        return Err(format!(
+                            "consulate_registry.rs | ConsulateRegistry::new() | Name '{}' is used by both {} and {}.",
+                            key, consulates[other].id, consulate.id
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(ConsulateRegistry { consulates, lookup })
+    }
+
+    pub fn load(path: &Path) -> Result<ConsulateRegistry, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!(
+            "consulate_registry.rs | ConsulateRegistry::load(path: {:?}) | Error occured when reading the registry. | {:?}",
+            path, e
+        ))?;
+        let consulates: Vec<Consulate> = serde_json::from_str(&text).map_err(|e| format!(
+            "consulate_registry.rs | ConsulateRegistry::load(path: {:?}) | The registry file is invalid. | {:?}",
+            path, e
+        ))?;
+        ConsulateRegistry::new(consulates)
+    }
+
+    // Finds the post a city cell of the appointment table refers to
+    pub fn resolve(&self, text: &str) -> Option<&Consulate> {
+        self.lookup
+            .get(&match_key(text))
+            .map(|i| &self.consulates[*i])
+    }
+
+    pub fn get(&self, id: &ConsulateId) -> Option<&Consulate> {
+        self.consulates.iter().find(|c| &c.id == id)
+    }
+
+    pub fn consulates(&self) -> &[Consulate] {
+        &self.consulates
+    }
+}
+
+static CONSULATE_REGISTRY: OnceLock<ConsulateRegistry> = OnceLock::new();
+
+// Loaded once at startup next to the runtime config, a bad file stops the bot
+pub fn init_consulate_registry(path: &Path) -> Result<&'static ConsulateRegistry, String> {
+    let registry = ConsulateRegistry::load(path)?;
+    Ok(install_consulate_registry(registry))
+}
+
+// For callers that build the registry themselves, e.g. from an embedded file
+pub fn install_consulate_registry(registry: ConsulateRegistry) -> &'static ConsulateRegistry {
+    CONSULATE_REGISTRY.get_or_init(|| registry)
+}
+
+pub fn consulate_registry() -> &'static ConsulateRegistry {
+    CONSULATE_REGISTRY
+        .get()
+        .expect("init_consulate_registry must run before the loops start")
+}
@@ -0,0 +1,489 @@
+// Encrypted-at-rest credential vault shared by both bots
+// Accounts, API keys and proxy auth are stored in one file encrypted with
+// XChaCha20-Poly1305, unlocked by a passphrase (Argon2id) or a 32 byte key file.
+// The bots read it once at startup, the CLI adds, lists, rotates and removes entries
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const VAULT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Account,
+    ApiKey,
+    Proxy,
+}
+
+// username and proxy are only used by some kinds, secret is the password or key
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VaultEntry {
+    pub kind: EntryKind,
+    pub username: Option<String>,
+    // Written for real, the whole VaultContents is encrypted before it is saved
+    #[serde(serialize_with = "serialize_exposed")]
+    pub secret: Secret<String>,
+    #[serde(serialize_with = "serialize_exposed_opt")]
+    pub proxy: Option<Secret<String>>,
+    pub user_payment_id: Option<String>,
+    pub rotated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct VaultContents {
+    pub entries: BTreeMap<String, VaultEntry>,
+}
+
+// What is written to disk, everything but the salt and nonce is ciphertext
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+pub enum VaultKeySource {
+    Passphrase(Secret<String>),
+    KeyFile(PathBuf),
+}
+
+pub struct Vault {
+    path: PathBuf,
+    key_source: VaultKeySource,
+    salt: [u8; 16],
+    pub contents: VaultContents,
+}
+
+// VAULT_KEY_FILE wins over VAULT_PASSPHRASE, init and open_from_env both go through here
+fn key_source_from_env() -> Option<VaultKeySource> {
+    if let Ok(key_file) = std::env::var("VAULT_KEY_FILE") {
+        Some(VaultKeySource::KeyFile(PathBuf::from(key_file)))
+    } else if let Ok(passphrase) = std::env::var("VAULT_PASSPHRASE") {
+        Some(VaultKeySource::Passphrase(Secret::new(passphrase)))
+    } else {
+        None
+    }
+}
+
+// Passphrases are stretched with Argon2id, key files are used as is
+fn derive_key(source: &VaultKeySource, salt: &[u8]) -> Result<[u8; 32], String> {
+    let mut key = [0u8; 32];
+    match source {
+        VaultKeySource::Passphrase(passphrase) => {
+            Argon2::default()
+                .hash_password_into(passphrase.expose().as_bytes(), salt, &mut key)
+                .map_err(|e| format!(
+                    "credential_vault.rs | derive_key | Error occured when deriving the key. | {:?}",
+                    e
+                ))?;
+        }
+        VaultKeySource::KeyFile(path) => {
+            let bytes = std::fs::read(path).map_err(|e| format!(
+                "credential_vault.rs | derive_key(key_file: {:?}) | Error occured when reading the key file. | {:?}",
+                path, e
+            ))?;
+            if bytes.len() != 32 {
+                return Err(format!(
+                    "credential_vault.rs | derive_key(key_file: {:?}) | The key file must be exactly 32 bytes, found {}.",
+                    path,
+                    bytes.len()
+                ));
+            }
+            key.copy_from_slice(&bytes);
+        }
+    }
+    Ok(key)
+}
+
+impl Vault {
+    // Starts an empty vault, nothing is written until save()
+    pub fn create(path: PathBuf, key_source: VaultKeySource) -> Vault {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        Vault {
+            path,
+            key_source,
+            salt,
+            contents: VaultContents::default(),
+        }
+    }
+
+    // A wrong passphrase or key shows up as a decryption error, never as garbage data
+    pub fn open(path: PathBuf, key_source: VaultKeySource) -> Result<Vault, String> {
+        let text = std::fs::read_to_string(&path).map_err(|e| format!(
+            "credential_vault.rs | open(path: {:?}) | Error occured when reading the vault. | {:?}",
+            path, e
+        ))?;
+        let file: VaultFile = serde_json::from_str(&text).map_err(|e| format!(
+            "credential_vault.rs | open(path: {:?}) | The vault file is corrupted. | {:?}",
+            path, e
+        ))?;
+        if file.version != VAULT_VERSION {
+            return Err(format!(
+                "credential_vault.rs | open(path: {:?}) | Unsupported vault version {}.",
+                path, file.version
+            ));
+        }
+
+        let decode = |field: &str| BASE64.decode(field).map_err(|e| format!(
+            "credential_vault.rs | open(path: {:?}) | The vault file is corrupted. | {:?}",
+            path, e
+        ));
+        let salt_bytes = decode(&file.salt)?;
+        let nonce = decode(&file.nonce)?;
+        let ciphertext = decode(&file.ciphertext)?;
+        if salt_bytes.len() != 16 || nonce.len() != 24 {
+            return Err(format!(
+                "credential_vault.rs | open(path: {:?}) | The vault file is corrupted.",
+                path
+            ));
+        }
+        let mut salt = [0u8; 16];
+        salt.copy_from_slice(&salt_bytes);
+
+        let key = derive_key(&key_source, &salt)?;
+        let plaintext = XChaCha20Poly1305::new(&key.into())
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| format!(
+                "credential_vault.rs | open(path: {:?}) | Wrong passphrase or key file.",
+                path
+            ))?;
+        let contents = serde_json::from_slice(&plaintext).map_err(|e| format!(
+            "credential_vault.rs | open(path: {:?}) | The decrypted vault is corrupted. | {:?}",
+            path, e
+        ))?;
+
+        Ok(Vault {
+            path,
+            key_source,
+            salt,
+            contents,
+        })
+    }
+
+    // Unlocks with VAULT_KEY_FILE if set, otherwise with VAULT_PASSPHRASE
+    pub fn open_from_env(path: PathBuf) -> Result<Vault, String> {
+        let key_source = key_source_from_env().ok_or(
+            "credential_vault.rs | open_from_env | Neither VAULT_KEY_FILE nor VAULT_PASSPHRASE is set.".to_string(),
+        )?;
+        Vault::open(path, key_source)
+    }
+
+    // Encrypts with a fresh nonce every time and replaces the file atomically
+    pub fn save(&self) -> Result<(), String> {
+        let plaintext = serde_json::to_vec(&self.contents).map_err(|e| format!(
+            "credential_vault.rs | save(path: {:?}) | Error occured when serializing the vault. | {:?}",
+            self.path, e
+        ))?;
+
+        let key = derive_key(&self.key_source, &self.salt)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = XChaCha20Poly1305::new(&key.into())
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|e| format!(
+                "credential_vault.rs | save(path: {:?}) | Error occured when encrypting the vault. | {:?}",
+                self.path, e
+            ))?;
+
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            salt: BASE64.encode(self.salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        let text = serde_json::to_string_pretty(&file).unwrap_or_default();
+
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, text)
+            .and_then(|_| restrict_permissions(&tmp_path))
+            .and_then(|_| std::fs::rename(&tmp_path, &self.path))
+            .map_err(|e| format!(
+                "credential_vault.rs | save(path: {:?}) | Error occured when writing the vault. | {:?}",
+                self.path, e
+            ))
+    }
+
+    // Encrypts other local state (e.g. checkpointed cookies) under the vault key
+    pub fn seal(&self, plaintext: &[u8]) -> Result<String, String> {
+        let key = derive_key(&self.key_source, &self.salt)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = XChaCha20Poly1305::new(&key.into())
+            .encrypt(&nonce, plaintext)
+            .map_err(|e| format!("credential_vault.rs | seal | Error occured when encrypting. | {:?}", e))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(BASE64.encode(sealed))
+    }
+
+    pub fn unseal(&self, sealed: &str) -> Result<Vec<u8>, String> {
+        let bytes = BASE64
+            .decode(sealed)
+            .map_err(|e| format!("credential_vault.rs | unseal | The sealed data is corrupted. | {:?}", e))?;
+        if bytes.len() < 24 {
+            return Err("credential_vault.rs | unseal | The sealed data is corrupted.".to_string());
+        }
+        let (nonce, ciphertext) = bytes.split_at(24);
+
+        let key = derive_key(&self.key_source, &self.salt)?;
+        XChaCha20Poly1305::new(&key.into())
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "credential_vault.rs | unseal | The data was sealed with another key.".to_string())
+    }
+
+    pub fn entries_of(&self, kind: EntryKind) -> impl Iterator<Item = (&String, &VaultEntry)> {
+        self.contents
+            .entries
+            .iter()
+            .filter(move |(_, entry)| entry.kind == kind)
+    }
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+// Startup readers used by the bots
+
+pub fn load_accounts(vault: &Vault) -> Vec<Account> {
+    vault
+        .entries_of(EntryKind::Account)
+        .map(|(_, entry)| Account {
+            username: entry.username.clone().unwrap_or_default(),
+            password: entry.secret.clone(),
+            proxy_string: entry.proxy.clone().unwrap_or_default(),
+            user_payment_id: entry.user_payment_id.clone().unwrap_or_default(),
+            cookie: Secret::default(),
+        })
+        .collect()
+}
+
+// (entry name, key), the name is what shows up in logs and metrics
+pub fn load_api_keys(vault: &Vault) -> Vec<(String, Secret<String>)> {
+    vault
+        .entries_of(EntryKind::ApiKey)
+        .map(|(name, entry)| (name.clone(), entry.secret.clone()))
+        .collect()
+}
+
+// (proxy_username, proxy_password) of the first proxy entry
+pub fn load_proxy_credentials(vault: &Vault) -> Result<(Secret<String>, Secret<String>), String> {
+    vault
+        .entries_of(EntryKind::Proxy)
+        .next()
+        .map(|(_, entry)| {
+            (
+                Secret::new(entry.username.clone().unwrap_or_default()),
+                entry.secret.clone(),
+            )
+        })
+        .ok_or("credential_vault.rs | load_proxy_credentials | The vault has no proxy entry.".to_string())
+}
+
+// Secrets are read from the terminal without echo, never from the command line
+fn prompt_secret(prompt: &str) -> Result<Secret<String>, String> {
+    rpassword::prompt_password(prompt)
+        .map(Secret::new)
+        .map_err(|e| format!("credential_vault.rs | prompt_secret | Error occured when reading the secret. | {:?}", e))
+}
+
+// vault init | add account <name> <username> [user_payment_id] | add api_key <name>
+//   | add proxy <name> <username> | list | rotate <name> | remove <name>
+pub fn run_vault_cli(path: PathBuf, args: &[String]) -> Result<String, String> {
+    let usage = "Usage: vault init | add account <name> <username> [user_payment_id] | add api_key <name> | add proxy <name> <username> | list | rotate <name> | remove <name>";
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+    if args.as_slice() == ["init"] {
+        if path.exists() {
+            return Err(format!("{:?} already exists", path));
+        }
+        // Created with the key the bots will open it with, the passphrase is only
+        // prompted for (twice) when the environment names neither key
+        let key_source = match key_source_from_env() {
+            Some(key_source) => key_source,
+            None => {
+                let passphrase = prompt_secret("New vault passphrase: ")?;
+                let confirmation = prompt_secret("Repeat the passphrase: ")?;
+                if passphrase.expose() != confirmation.expose() {
+                    return Err("The passphrases do not match".to_string());
+                }
+                VaultKeySource::Passphrase(passphrase)
+            }
+        };
+        let created_with = match key_source {
+            VaultKeySource::KeyFile(_) => "VAULT_KEY_FILE",
+            VaultKeySource::Passphrase(_) => "the passphrase, set VAULT_PASSPHRASE to open it",
+        };
+        Vault::create(path, key_source).save()?;
+        return Ok(format!("Vault created, encrypted with {}", created_with));
+    }
+
+    let mut vault = Vault::open_from_env(path)?;
+    let now = chrono::Utc::now();
+
+    let message = match args.as_slice() {
+        ["add", "account", name, username, rest @ ..] => {
+            let secret = prompt_secret("Password: ")?;
+            let proxy = prompt_secret("Proxy (user:pass@host:port): ")?;
+            vault.contents.entries.insert(name.to_string(), VaultEntry {
+                kind: EntryKind::Account,
+                username: Some(username.to_string()),
+                secret,
+                proxy: Some(proxy),
+                user_payment_id: rest.first().map(|id| id.to_string()),
+                rotated_at: now,
+            });
+            format!("Added account {}", name)
+        }
+        ["add", "api_key", name] => {
+            let secret = prompt_secret("API key: ")?;
+            vault.contents.entries.insert(name.to_string(), VaultEntry {
+                kind: EntryKind::ApiKey,
+                username: None,
+                secret,
+                proxy: None,
+                user_payment_id: None,
+                rotated_at: now,
+            });
+            format!("Added API key {}", name)
+        }
+        ["add", "proxy", name, username] => {
+            let secret = prompt_secret("Proxy password: ")?;
+            vault.contents.entries.insert(name.to_string(), VaultEntry {
+                kind: EntryKind::Proxy,
+                username: Some(username.to_string()),
+                secret,
+                proxy: None,
+                user_payment_id: None,
+                rotated_at: now,
+            });
+            format!("Added proxy {}", name)
+        }
+        // Names, kinds and rotation dates only, secrets are never printed
+        ["list"] => {
+            let lines: Vec<String> = vault
+                .contents
+                .entries
+                .iter()
+                .map(|(name, entry)| format!(
+                    "{} | {:?} | {} | rotated {}",
+                    name,
+                    entry.kind,
+                    entry.username.as_deref().unwrap_or("-"),
+                    entry.rotated_at.format("%Y-%m-%d")
+                ))
+                .collect();
+            return Ok(lines.join("\n"));
+        }
+        ["rotate", name] => {
+            let entry = vault
+                .contents
+                .entries
+                .get_mut(*name)
+                .ok_or(format!("{} is not in the vault", name))?;
+            entry.secret = prompt_secret("New secret: ")?;
+            entry.rotated_at = now;
+            format!("Rotated {}", name)
+        }
+        ["remove", name] => {
+            vault
+                .contents
+                .entries
+                .remove(*name)
+                .ok_or(format!("{} is not in the vault", name))?;
+            format!("Removed {}", name)
+        }
+        _ => return Err(usage.to_string()),
+    };
+
+    vault.save()?;
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("vault-test-{}-{}", std::process::id(), name))
+    }
+
+    fn passphrase(text: &str) -> VaultKeySource {
+        VaultKeySource::Passphrase(Secret::new(text.to_string()))
+    }
+
+    #[test]
+    fn sealed_data_round_trips() {
+        let vault = Vault::create(temp_path("seal"), passphrase("correct horse"));
+        let sealed = vault.seal(b"_yatri_session=abc").unwrap();
+        assert!(!sealed.contains("abc"));
+        assert_eq!(vault.unseal(&sealed).unwrap(), b"_yatri_session=abc");
+        // A fresh nonce every time
+        assert_ne!(vault.seal(b"_yatri_session=abc").unwrap(), sealed);
+    }
+
+    #[test]
+    fn unsealing_with_another_key_fails() {
+        let vault = Vault::create(temp_path("seal-a"), passphrase("correct horse"));
+        let other = Vault::create(temp_path("seal-b"), passphrase("battery staple"));
+        let sealed = vault.seal(b"cookie").unwrap();
+        assert!(other.unseal(&sealed).is_err());
+        assert!(vault.unseal("not base64!").is_err());
+    }
+
+    #[test]
+    fn saved_vault_opens_only_with_its_key() {
+        let path = temp_path("file.json");
+        let mut vault = Vault::create(path.clone(), passphrase("correct horse"));
+        vault.contents.entries.insert("scrape-1".to_string(), VaultEntry {
+            kind: EntryKind::ApiKey,
+            username: None,
+            secret: Secret::new("key-123".to_string()),
+            proxy: None,
+            user_payment_id: None,
+            rotated_at: chrono::Utc::now(),
+        });
+        vault.save().unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("key-123"));
+
+        let opened = Vault::open(path.clone(), passphrase("correct horse")).unwrap();
+        assert_eq!(opened.contents.entries["scrape-1"].secret.expose(), "key-123");
+
+        let err = Vault::open(path.clone(), passphrase("wrong")).err().unwrap();
+        assert!(err.contains("Wrong passphrase or key file"), "{}", err);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn key_file_vault_round_trips() {
+        let key_path = temp_path("key");
+        std::fs::write(&key_path, [7u8; 32]).unwrap();
+        let path = temp_path("keyfile-vault.json");
+        Vault::create(path.clone(), VaultKeySource::KeyFile(key_path.clone())).save().unwrap();
+        assert!(Vault::open(path.clone(), VaultKeySource::KeyFile(key_path.clone())).is_ok());
+
+        std::fs::write(&key_path, [8u8; 32]).unwrap();
+        assert!(Vault::open(path.clone(), VaultKeySource::KeyFile(key_path.clone())).is_err());
+        // Not a key at all
+        std::fs::write(&key_path, b"short").unwrap();
+        assert!(Vault::open(path.clone(), VaultKeySource::KeyFile(key_path.clone())).is_err());
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&key_path);
+    }
+}
@@ -0,0 +1,102 @@
+// Graceful shutdown shared by both bots
+// Ctrl-C or SIGTERM cancels one token that every loop and scheduler watches:
+// they stop scheduling new work, whatever is in flight gets a deadline to
+// finish, then state and logs are flushed before the process exits
+
+use std::future::Future;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+pub struct ShutdownCoordinator {
+    token: CancellationToken,
+    tracker: TaskTracker,
+    drain_deadline: Duration,
+}
+
+impl ShutdownCoordinator {
+    pub fn new(drain_deadline: Duration) -> ShutdownCoordinator {
+        ShutdownCoordinator {
+            token: CancellationToken::new(),
+            tracker: TaskTracker::new(),
+            drain_deadline,
+        }
+    }
+
+    // Handed to every loop, cancelled once shutdown starts
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    // Tasks spawned here are the ones waited for while draining
+    pub fn spawn<F>(&self, name: &'static str, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tracker.spawn(async move {
+            task.await;
+            print_n_log(format!("Task {} stopped.", name), true, true);
+        });
+    }
+
+    // Cancels the token on the first Ctrl-C or SIGTERM
+    pub fn listen_for_signals(&self) {
+        let token = self.token.clone();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            print_n_log("Shutdown requested, draining in-flight work.".to_string(), true, true);
+            token.cancel();
+        });
+    }
+
+    // Waits for the shutdown signal, drains the tracked tasks and runs flush last
+    // Tasks still running after the deadline are abandoned, so flush always runs
+    pub async fn run_until_shutdown(self, flush: impl FnOnce()) {
+        self.token.cancelled().await;
+        self.tracker.close();
+
+        if timeout(self.drain_deadline, self.tracker.wait()).await.is_err() {
+            print_n_log(
+                format!(
+                    "{} task(s) did not finish within {:?}, abandoning them.",
+                    self.tracker.len(),
+                    self.drain_deadline
+                ),
+                true,
+                true,
+            );
+        }
+
+        flush();
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = sigterm.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+// Cooldown that ends early on shutdown, returns false when the loop should stop
+pub async fn sleep_or_cancelled(token: &CancellationToken, duration: Duration) -> bool {
+    tokio::select! {
+        _ = token.cancelled() => false,
+        _ = sleep(duration) => true,
+    }
+}
@@ -0,0 +1,454 @@
+// Locale aware date parsing for appointment pages
+// The portal is served in several languages, so month names are looked up in the
+// table of the page's lang attribute (falling back to every table), abbreviations
+// and missing diacritics are accepted ("Şub." and "Subat" both mean Şubat), and
+// day, month and year may come in any order ("5 Mart, 2025", "March 5, 2025",
+// "5 de marzo de 2025", "5. März 2025")
+
+use chrono::{Datelike, NaiveDate};
+use scraper::{Html, Selector};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateLocale {
+    English,
+    Turkish,
+    Spanish,
+    Portuguese,
+    French,
+    German,
+}
+
+pub const ALL_LOCALES: [DateLocale; 6] = [
+    DateLocale::Turkish,
+    DateLocale::English,
+    DateLocale::Spanish,
+    DateLocale::Portuguese,
+    DateLocale::French,
+    DateLocale::German,
+];
+
+// Month names without diacritics, lower case, followed by accepted alternative spellings
+fn month_table(locale: DateLocale) -> &'static [(&'static str, u32)] {
+    match locale {
+        DateLocale::English => &[
+            ("january", 1), ("february", 2), ("march", 3), ("april", 4),
+            ("may", 5), ("june", 6), ("july", 7), ("august", 8),
+            ("september", 9), ("october", 10), ("november", 11), ("december", 12),
+            ("sept", 9),
+        ],
+        DateLocale::Turkish => &[
+            ("ocak", 1), ("subat", 2), ("mart", 3), ("nisan", 4),
+            ("mayis", 5), ("haziran", 6), ("temmuz", 7), ("agustos", 8),
+            ("eylul", 9), ("ekim", 10), ("kasim", 11), ("aralik", 12),
+        ],
+        DateLocale::Spanish => &[
+            ("enero", 1), ("febrero", 2), ("marzo", 3), ("abril", 4),
+            ("mayo", 5), ("junio", 6), ("julio", 7), ("agosto", 8),
+            ("septiembre", 9), ("octubre", 10), ("noviembre", 11), ("diciembre", 12),
+            ("setiembre", 9),
+        ],
+        DateLocale::Portuguese => &[
+            ("janeiro", 1), ("fevereiro", 2), ("marco", 3), ("abril", 4),
+            ("maio", 5), ("junho", 6), ("julho", 7), ("agosto", 8),
+            ("setembro", 9), ("outubro", 10), ("novembro", 11), ("dezembro", 12),
+        ],
+        DateLocale::French => &[
+            ("janvier", 1), ("fevrier", 2), ("mars", 3), ("avril", 4),
+            ("mai", 5), ("juin", 6), ("juillet", 7), ("aout", 8),
+            ("septembre", 9), ("octobre", 10), ("novembre", 11), ("decembre", 12),
+        ],
+        DateLocale::German => &[
+            ("januar", 1), ("februar", 2), ("marz", 3), ("april", 4),
+            ("mai", 5), ("juni", 6), ("juli", 7), ("august", 8),
+            ("september", 9), ("oktober", 10), ("november", 11), ("dezember", 12),
+            ("maerz", 3), ("janner", 1),
+        ],
+    }
+}
+
+// Words that sit between the date parts in some languages ("5 de marzo de 2025")
+const FILLER_WORDS: [&str; 6] = ["de", "del", "of", "the", "le", "den"];
+
+// Shortest abbreviation accepted, "ma" could be March or May
+const MIN_ABBREVIATION_LEN: usize = 3;
+
+// What the portal writes in the date cell of a post without free slots, normalized
+// Checked in every language, like the month names, in case the lang attribute is wrong
+const NO_APPOINTMENT_MARKERS: [&str; 9] = [
+    "randevu yok",
+    "uygun randevu yok",
+    "no appointments available",
+    "no available appointments",
+    "no hay citas disponibles",
+    "nenhum agendamento disponivel",
+    "sem horarios disponiveis",
+    "aucun rendez-vous disponible",
+    "keine termine verfugbar",
+];
+
+// Maps a lang attribute such as "tr", "en-US" or "pt_BR" to a locale
+pub fn locale_from_lang(lang: &str) -> Option<DateLocale> {
+    let primary = lang
+        .split(|c| c == '-' || c == '_')
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+
+    match primary.as_str() {
+        "en" => Some(DateLocale::English),
+        "tr" => Some(DateLocale::Turkish),
+        "es" => Some(DateLocale::Spanish),
+        "pt" => Some(DateLocale::Portuguese),
+        "fr" => Some(DateLocale::French),
+        "de" => Some(DateLocale::German),
+        _ => None,
+    }
+}
+
+// Reads <html lang=".."> from the page, the portal has always defaulted to Turkish
+pub fn detect_locale(document: &Html) -> DateLocale {
+    let html_selector = Selector::parse("html").unwrap();
+    document
+        .select(&html_selector)
+        .next()
+        .and_then(|html| html.value().attr("lang"))
+        .and_then(locale_from_lang)
+        .unwrap_or(DateLocale::Turkish)
+}
+
+// Lower case with the diacritics of the supported languages folded to ASCII
+// Also used to match consulate names, see consulate-registry.rs
+pub fn normalize(word: &str) -> String {
+    let mut normalized = String::with_capacity(word.len());
+    for c in word.to_lowercase().chars() {
+        match c {
+            'ş' => normalized.push('s'),
+            'ı' => normalized.push('i'),
+            'ğ' => normalized.push('g'),
+            'ç' => normalized.push('c'),
+            'ñ' => normalized.push('n'),
+            'ß' => normalized.push_str("ss"),
+            'à' | 'á' | 'â' | 'ã' | 'ä' => normalized.push('a'),
+            'è' | 'é' | 'ê' | 'ë' => normalized.push('e'),
+            'ì' | 'í' | 'î' | 'ï' => normalized.push('i'),
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' => normalized.push('o'),
+            'ù' | 'ú' | 'û' | 'ü' => normalized.push('u'),
+            // Combining dot left over from lower casing the Turkish 'İ'
+            '\u{0307}' => {}
+            _ => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+// True for a date cell that says the post has no appointment rather than a date
+pub fn is_no_appointment_text(text: &str) -> bool {
+    let normalized = normalize(text);
+    let words: Vec<&str> = normalized
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| c == '.' || c == '!'))
+        .filter(|word| !word.is_empty())
+        .collect();
+    NO_APPOINTMENT_MARKERS.contains(&words.join(" ").as_str())
+}
+
+// Canonical (first listed) name of a month, without diacritics
+pub fn month_name(locale: DateLocale, month: u32) -> Option<&'static str> {
+    month_table(locale)
+        .iter()
+        .find(|(_, m)| *m == month)
+        .map(|(name, _)| *name)
+}
+
+// Writes a date the way the portal does in that language, "5 mart 2026" or
+// "march 5, 2026", parse_localized_date reads it back to the same date
+pub fn format_localized_date(date: NaiveDate, locale: DateLocale) -> String {
+    let month = month_name(locale, date.month()).unwrap_or_default();
+    // Always four digits, the parser only takes a four digit token as the year
+    let year = format!("{:04}", date.year());
+    match locale {
+        DateLocale::English => format!("{} {}, {}", month, date.day(), year),
+        DateLocale::Spanish | DateLocale::Portuguese => {
+            format!("{} de {} de {}", date.day(), month, year)
+        }
+        DateLocale::German => format!("{}. {} {}", date.day(), month, year),
+        DateLocale::Turkish | DateLocale::French => {
+            format!("{} {} {}", date.day(), month, year)
+        }
+    }
+}
+
+// Exact name first, then an abbreviation that matches exactly one month
+fn lookup_month(word: &str, locale: DateLocale) -> Option<u32> {
+    let table = month_table(locale);
+    if let Some((_, month)) = table.iter().find(|(name, _)| *name == word) {
+        return Some(*month);
+    }
+    if word.chars().count() < MIN_ABBREVIATION_LEN {
+        return None;
+    }
+
+    let mut matches = table
+        .iter()
+        .filter(|(name, _)| name.starts_with(word))
+        .map(|(_, month)| *month);
+    let first = matches.next()?;
+    // Aliases of the same month are not an ambiguity
+    if matches.all(|month| month == first) {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+// The selected locale wins, the others are a fallback for pages with a wrong lang
+fn find_month(word: &str, locale: DateLocale) -> Option<u32> {
+    lookup_month(word, locale).or_else(|| {
+        ALL_LOCALES
+            .iter()
+            .filter(|other| **other != locale)
+            .find_map(|other| lookup_month(word, *other))
+    })
+}
+
+// "5th", "1er", "5." and "5º" are all day 5
+fn parse_day(word: &str) -> Option<u32> {
+    let digits: String = word.chars().take_while(|c| c.is_ascii_digit()).collect();
+    let suffix = &word[digits.len()..];
+    if digits.is_empty() || digits.len() > 2 {
+        return None;
+    }
+    match suffix {
+        "" | "st" | "nd" | "rd" | "th" | "er" | "e" | "º" | "ª" => digits.parse().ok(),
+        _ => None,
+    }
+}
+
+pub fn parse_localized_date(
+    date_str: &str,
+    locale: DateLocale,
+) -> Result<NaiveDate, Box<dyn std::error::Error>> {
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+
+    for raw in date_str.split(|c: char| c.is_whitespace() || c == ',' || c == '/') {
+        let word = normalize(raw.trim_matches('.'));
+        if word.is_empty() || FILLER_WORDS.contains(&word.as_str()) {
+            continue;
+        }
+
+        if word.len() == 4 && word.chars().all(|c| c.is_ascii_digit()) {
+            if year.replace(word.parse::<i32>()?).is_some() {
+                return Err(format!("Two years in date: {}", date_str).into());
+            }
+        } else if let Some(d) = parse_day(&word) {
+            if day.replace(d).is_some() {
+                return Err(format!("Two days in date: {}", date_str).into());
+            }
+        } else if let Some(m) = find_month(&word, locale) {
+            if month.replace(m).is_some() {
+                return Err(format!("Two months in date: {}", date_str).into());
+            }
+        } else {
+            return Err(format!("Invalid date part '{}' in: {}", raw, date_str).into());
+        }
+    }
+
+    match (year, month, day) {
+        // from_ymd_opt rejects days that do not exist, e.g. "31 Şubat"
+        (Some(year), Some(month), Some(day)) => Ok(NaiveDate::from_ymd_opt(year, month, day)
+            .ok_or(format!("Invalid date: {}", date_str))?),
+        _ => Err(format!("Invalid date format: {}", date_str).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    // (input, locale, expected)
+    const VALID: &[(&str, DateLocale, (i32, u32, u32))] = &[
+        // Turkish, the portal's default
+        ("5 Mart, 2025", DateLocale::Turkish, (2025, 3, 5)),
+        ("17 Şubat 2025", DateLocale::Turkish, (2025, 2, 17)),
+        ("17 Şub. 2025", DateLocale::Turkish, (2025, 2, 17)),
+        ("17 Subat 2025", DateLocale::Turkish, (2025, 2, 17)),
+        ("3 Eylul 2025", DateLocale::Turkish, (2025, 9, 3)),
+        ("1 Mayıs 2025", DateLocale::Turkish, (2025, 5, 1)),
+        ("9 AĞUSTOS 2025", DateLocale::Turkish, (2025, 8, 9)),
+        // English
+        ("March 5, 2025", DateLocale::English, (2025, 3, 5)),
+        ("5 March 2025", DateLocale::English, (2025, 3, 5)),
+        ("5th of March, 2025", DateLocale::English, (2025, 3, 5)),
+        ("Sept 3, 2025", DateLocale::English, (2025, 9, 3)),
+        ("Feb. 17, 2025", DateLocale::English, (2025, 2, 17)),
+        // Spanish
+        ("5 de marzo de 2025", DateLocale::Spanish, (2025, 3, 5)),
+        ("3 de setiembre de 2025", DateLocale::Spanish, (2025, 9, 3)),
+        ("1 ene. 2025", DateLocale::Spanish, (2025, 1, 1)),
+        // Portuguese
+        ("5 de março de 2025", DateLocale::Portuguese, (2025, 3, 5)),
+        ("5 de marco de 2025", DateLocale::Portuguese, (2025, 3, 5)),
+        ("1º de maio de 2025", DateLocale::Portuguese, (2025, 5, 1)),
+        // French
+        ("5 mars 2025", DateLocale::French, (2025, 3, 5)),
+        ("1er févr. 2025", DateLocale::French, (2025, 2, 1)),
+        ("15 aout 2025", DateLocale::French, (2025, 8, 15)),
+        ("3 décembre 2025", DateLocale::French, (2025, 12, 3)),
+        // German
+        ("5. März 2025", DateLocale::German, (2025, 3, 5)),
+        ("5. Marz 2025", DateLocale::German, (2025, 3, 5)),
+        ("5. Maerz 2025", DateLocale::German, (2025, 3, 5)),
+        ("1. Jänner 2025", DateLocale::German, (2025, 1, 1)),
+        ("24. Dez. 2025", DateLocale::German, (2025, 12, 24)),
+        // Year first
+        ("2025 Mart 5", DateLocale::Turkish, (2025, 3, 5)),
+        // A page with the wrong lang still parses through the other tables
+        ("March 5, 2025", DateLocale::Turkish, (2025, 3, 5)),
+    ];
+
+    const INVALID: &[(&str, DateLocale)] = &[
+        ("31 Şubat 2025", DateLocale::Turkish),
+        ("30 February 2025", DateLocale::English),
+        ("0 Mart 2025", DateLocale::Turkish),
+        // Too short to be an abbreviation
+        ("5 Ma 2025", DateLocale::English),
+        // juin or juillet
+        ("5 jui 2025", DateLocale::French),
+        ("5 Mart", DateLocale::Turkish),
+        ("5 Mart 2025 6", DateLocale::Turkish),
+        ("Randevu yok", DateLocale::Turkish),
+        ("", DateLocale::English),
+    ];
+
+    #[test]
+    fn parses_every_locale() {
+        for (input, locale, (year, month, day)) in VALID {
+            let parsed = parse_localized_date(input, *locale)
+                .unwrap_or_else(|e| panic!("{:?} {:?}: {}", input, locale, e));
+            assert_eq!(parsed, ymd(*year, *month, *day), "{:?} {:?}", input, locale);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for (input, locale) in INVALID {
+            assert!(parse_localized_date(input, *locale).is_err(), "{:?} {:?}", input, locale);
+        }
+    }
+
+    #[test]
+    fn rejects_days_past_the_end_of_the_month() {
+        for input in ["31 Şubat 2025", "29 Şubat 2025", "30 Şub. 2024", "31 Nisan 2025", "31 Eylül 2025"] {
+            assert!(parse_localized_date(input, DateLocale::Turkish).is_err(), "{}", input);
+        }
+        // 2024 is a leap year
+        assert_eq!(parse_localized_date("29 Şubat 2024", DateLocale::Turkish).unwrap(), ymd(2024, 2, 29));
+    }
+
+    fn any_locale() -> impl Strategy<Value = DateLocale> {
+        prop::sample::select(ALL_LOCALES.to_vec())
+    }
+
+    // Four digit years only, the parser reads no other year format
+    fn any_date() -> impl Strategy<Value = NaiveDate> {
+        (1000i32..=9999, 1u32..=12, 1u32..=31).prop_filter_map("no such day", |(year, month, day)| {
+            NaiveDate::from_ymd_opt(year, month, day)
+        })
+    }
+
+    // Every order of day, month and year the parser accepts
+    const ORDERS: [[usize; 3]; 6] = [[0, 1, 2], [1, 0, 2], [2, 1, 0], [2, 0, 1], [0, 2, 1], [1, 2, 0]];
+
+    proptest! {
+        #[test]
+        fn formatted_dates_parse_back(date in any_date(), locale in any_locale()) {
+            let formatted = format_localized_date(date, locale);
+            prop_assert_eq!(parse_localized_date(&formatted, locale).ok(), Some(date), "{}", formatted);
+        }
+
+        #[test]
+        fn parts_parse_in_any_order(
+            date in any_date(),
+            locale in any_locale(),
+            order in prop::sample::select(ORDERS.to_vec()),
+            separator in prop::sample::select(vec![" ", ", ", " / "]),
+        ) {
+            let parts = [
+                date.day().to_string(),
+                month_name(locale, date.month()).unwrap().to_string(),
+                format!("{:04}", date.year()),
+            ];
+            let text = order.iter().map(|i| parts[*i].as_str()).collect::<Vec<_>>().join(separator);
+            prop_assert_eq!(parse_localized_date(&text, locale).ok(), Some(date), "{}", text);
+        }
+
+        // Exactly the days that exist are accepted, "31 Şubat" is not
+        #[test]
+        fn accepts_exactly_the_existing_days(
+            year in 1000i32..=9999,
+            month in 1u32..=12,
+            day in 0u32..=99,
+            locale in any_locale(),
+        ) {
+            let text = format!("{} {} {}", day, month_name(locale, month).unwrap(), year);
+            prop_assert_eq!(
+                parse_localized_date(&text, locale).ok(),
+                NaiveDate::from_ymd_opt(year, month, day),
+                "{}",
+                text
+            );
+        }
+
+        // Whatever the page says, parsing never panics and anything it accepts round-trips
+        #[test]
+        fn arbitrary_text_never_panics(text in "\\PC{0,40}", locale in any_locale()) {
+            if let Ok(date) = parse_localized_date(&text, locale) {
+                let formatted = format_localized_date(date, locale);
+                prop_assert_eq!(parse_localized_date(&formatted, locale).ok(), Some(date), "{}", formatted);
+            }
+        }
+    }
+
+    #[test]
+    fn recognizes_no_appointment_cells() {
+        for text in [
+            "Randevu yok",
+            "RANDEVU YOK.",
+            "No appointments available",
+            "No hay citas disponibles",
+            "Nenhum agendamento disponível",
+            "Aucun rendez-vous disponible",
+            "Keine Termine verfügbar",
+        ] {
+            assert!(is_no_appointment_text(text), "{}", text);
+        }
+        for text in ["5 Mart, 2026", "Randevu", "yok", ""] {
+            assert!(!is_no_appointment_text(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn detects_locale_from_lang() {
+        let cases = [
+            (r#"<html lang="tr"><body></body></html>"#, DateLocale::Turkish),
+            (r#"<html lang="en-US"><body></body></html>"#, DateLocale::English),
+            (r#"<html lang="es"><body></body></html>"#, DateLocale::Spanish),
+            (r#"<html lang="pt_BR"><body></body></html>"#, DateLocale::Portuguese),
+            (r#"<html lang="FR"><body></body></html>"#, DateLocale::French),
+            (r#"<html lang="de-DE"><body></body></html>"#, DateLocale::German),
+            // Missing or unknown lang falls back to Turkish
+            ("<html><body></body></html>", DateLocale::Turkish),
+            (r#"<html lang="ja"><body></body></html>"#, DateLocale::Turkish),
+        ];
+        for (html, expected) in cases {
+            assert_eq!(detect_locale(&Html::parse_document(html)), expected, "{}", html);
+        }
+    }
+}
@@ -0,0 +1,480 @@
+// Notifications for earlier appointment dates
+// Subscribes to the appointment events (see appointment-history.rs) and tells the
+// applicants through their own channels: SMTP email, a generic webhook, a Telegram
+// bot or a desktop notify-send. Each applicant's preferences decide which dates they
+// could take, and the same post and date is sent once per dedup window.
+// Every endpoint (SMTP host and port, webhook URL, Telegram API base) comes from the
+// config, so the channels can be pointed at local stand-ins such as the one below
+
+use axum::{body::Bytes, http::Uri, Router};
+use chrono::{NaiveDate, Utc};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use super::applicant_preferences::{ApplicantPreferences, Verdict};
+use super::appointment_history::{subscribe_appointment_events, AppointmentEvent};
+use super::consulate_registry::{consulate_registry, ConsulateId};
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(15);
+
+fn default_telegram_api() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+fn default_lang() -> String {
+    "en".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_dedup_window_secs() -> u64 {
+    6 * 60 * 60
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelConfig {
+    Email {
+        smtp_host: String,
+        smtp_port: u16,
+        // Off only for local stand-in servers
+        #[serde(default = "default_true")]
+        starttls: bool,
+        username: Option<String>,
+        password: Option<Secret<String>>,
+        from: String,
+        to: String,
+    },
+    Webhook {
+        url: String,
+    },
+    Telegram {
+        #[serde(default = "default_telegram_api")]
+        api_base: String,
+        bot_token: Secret<String>,
+        chat_id: String,
+    },
+    Desktop,
+}
+
+impl ChannelConfig {
+    fn kind(&self) -> &'static str {
+        match self {
+            ChannelConfig::Email { .. } => "email",
+            ChannelConfig::Webhook { .. } => "webhook",
+            ChannelConfig::Telegram { .. } => "telegram",
+            ChannelConfig::Desktop => "desktop",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ApplicantNotifications {
+    pub applicant: String,
+    // Language of the consulate names in the message
+    #[serde(default = "default_lang")]
+    pub lang: String,
+    // Which appointments this applicant can take, see applicant-preferences.rs
+    #[serde(default)]
+    pub preferences: ApplicantPreferences,
+    pub channels: Vec<ChannelConfig>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct NotifierConfig {
+    #[serde(default = "default_dedup_window_secs")]
+    pub dedup_window_secs: u64,
+    pub applicants: Vec<ApplicantNotifications>,
+}
+
+impl Validate for NotifierConfig {
+    fn validate(&self) -> Result<(), String> {
+        for applicant in &self.applicants {
+            if applicant.channels.is_empty() {
+                return Err(format!("applicant {} has no channels", applicant.applicant));
+            }
+            applicant
+                .preferences
+                .validate()
+                .map_err(|e| format!("applicant {} has invalid preferences: {}", applicant.applicant, e))?;
+        }
+        Ok(())
+    }
+}
+
+pub const NOTIFIER_CONFIG_ENV_PREFIX: &str = "VISA_NOTIFY";
+static NOTIFIER_CONFIG: OnceLock<LiveConfig<NotifierConfig>> = OnceLock::new();
+
+// Hot reloaded like the visa config, pass the result to watch_config
+pub fn init_notifier_config(path: &PathBuf) -> Result<&'static LiveConfig<NotifierConfig>, String> {
+    let config = load_config::<NotifierConfig>(path, NOTIFIER_CONFIG_ENV_PREFIX)?;
+    Ok(NOTIFIER_CONFIG.get_or_init(|| LiveConfig::new(config)))
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Alert {
+    pub applicant: String,
+    pub consulate: ConsulateId,
+    pub consulate_name: String,
+    pub date: NaiveDate,
+    pub previous: Option<NaiveDate>,
+    pub text: String,
+}
+
+impl Alert {
+    fn new(applicant: &ApplicantNotifications, consulate: &ConsulateId, date: NaiveDate, previous: Option<NaiveDate>) -> Alert {
+        let consulate_name = consulate_registry()
+            .get(consulate)
+            .map(|c| c.display_name(&applicant.lang).to_string())
+            .unwrap_or_else(|| consulate.to_string());
+        let text = match previous {
+            Some(previous) => format!("Earlier appointment at {}: {} (was {})", consulate_name, date, previous),
+            None => format!("Appointment available at {}: {}", consulate_name, date),
+        };
+        Alert {
+            applicant: applicant.applicant.clone(),
+            consulate: consulate.clone(),
+            consulate_name,
+            date,
+            previous,
+            text,
+        }
+    }
+}
+
+async fn send_email(channel: &ChannelConfig, alert: &Alert) -> Result<(), String> {
+    let ChannelConfig::Email { smtp_host, smtp_port, starttls, username, password, from, to } = channel else {
+        return Err("not an email channel".to_string());
+    };
+
+    let message = Message::builder()
+        .from(from.parse::<Mailbox>().map_err(|e| format!("{:?}", e))?)
+        .to(to.parse::<Mailbox>().map_err(|e| format!("{:?}", e))?)
+        .subject(format!("Visa appointment: {} on {}", alert.consulate_name, alert.date))
+        .body(alert.text.clone())
+        .map_err(|e| format!("{:?}", e))?;
+
+    let builder = if *starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(smtp_host).map_err(|e| format!("{:?}", e))?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(smtp_host)
+    };
+    let mut transport = builder.port(*smtp_port).timeout(Some(SEND_TIMEOUT));
+    if let (Some(username), Some(password)) = (username, password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.expose().clone()));
+    }
+
+    transport
+        .build()
+        .send(message)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("{:?}", e))
+}
+
+async fn send_http(client: &reqwest::Client, url: &str, body: serde_json::Value) -> Result<(), String> {
+    let res = client
+        .post(url)
+        .json(&body)
+        .timeout(SEND_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("{:?}", e.without_url()))?;
+    if !res.status().is_success() {
+        return Err(format!("status {}", res.status()));
+    }
+    Ok(())
+}
+
+async fn send_desktop(alert: &Alert) -> Result<(), String> {
+    let status = tokio::process::Command::new("notify-send")
+        .arg("Visa appointment")
+        .arg(&alert.text)
+        .status()
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+    if !status.success() {
+        return Err(format!("notify-send exited with {}", status));
+    }
+    Ok(())
+}
+
+// Errors never contain the bot token or SMTP password, both stay in their Secret
+async fn send(client: &reqwest::Client, channel: &ChannelConfig, alert: &Alert) -> Result<(), String> {
+    match channel {
+        ChannelConfig::Email { .. } => send_email(channel, alert).await,
+        ChannelConfig::Webhook { url } => {
+            send_http(client, url, serde_json::to_value(alert).unwrap_or_default()).await
+        }
+        ChannelConfig::Telegram { api_base, bot_token, chat_id } => {
+            let url = format!("{}/bot{}/sendMessage", api_base.trim_end_matches('/'), bot_token.expose());
+            let body = serde_json::json!({ "chat_id": chat_id, "text": alert.text });
+            send_http(client, &url, body).await.map_err(|e| bot_token.scrub(&e))
+        }
+        ChannelConfig::Desktop => send_desktop(alert).await,
+    }
+}
+
+// (applicant, consulate, date) -> when it was last sent
+#[derive(Default)]
+struct Dedup {
+    sent: HashMap<(String, ConsulateId, NaiveDate), Instant>,
+}
+
+impl Dedup {
+    // True when the alert was not sent inside the window, and marks it sent
+    fn first_in_window(&mut self, alert: &Alert, window: Duration) -> bool {
+        self.sent.retain(|_, sent_at| sent_at.elapsed() < window);
+        let key = (alert.applicant.clone(), alert.consulate.clone(), alert.date);
+        if self.sent.contains_key(&key) {
+            return false;
+        }
+        self.sent.insert(key, Instant::now());
+        true
+    }
+}
+
+// Alerts the event is worth, one per applicant whose preferences accept the date
+// A date can enter an applicant's window by moving earlier or by slipping later
+// (e.g. out of min_notice), so both are evaluated. Disappearances stay in the log
+fn alerts_for<'a>(
+    config: &'a NotifierConfig,
+    event: &AppointmentEvent,
+    today: NaiveDate,
+) -> Vec<(&'a ApplicantNotifications, Alert)> {
+    let (observation, previous) = match event {
+        AppointmentEvent::NewEarliest { observation, previous } => (observation, *previous),
+        // The previous date was better, the alert only announces the new one
+        AppointmentEvent::Slipped { observation, .. } => (observation, None),
+        AppointmentEvent::Disappeared { .. } => return Vec::new(),
+    };
+
+    config
+        .applicants
+        .iter()
+        .filter(|applicant| {
+            applicant
+                .preferences
+                .evaluate(&observation.consulate, observation.earliest_date, today)
+                == Verdict::Accept
+        })
+        .map(|applicant| {
+            let alert = Alert::new(applicant, &observation.consulate, observation.earliest_date, previous);
+            (applicant, alert)
+        })
+        .collect()
+}
+
+async fn notify(client: &reqwest::Client, config: &NotifierConfig, dedup: &mut Dedup, event: &AppointmentEvent) {
+    let window = Duration::from_secs(config.dedup_window_secs);
+
+    for (applicant, alert) in alerts_for(config, event, Utc::now().date_naive()) {
+        if !dedup.first_in_window(&alert, window) {
+            continue;
+        }
+
+        for channel in &applicant.channels {
+            if let Err(err) = send(client, channel, &alert).await {
+                print_n_log(
+                    format!(
+                        "notifier.rs | notify(applicant: {}, channel: {}) | Error occured when sending the alert. | {}",
+                        applicant.applicant, channel.kind(), err
+                    ),
+                    true,
+                    true,
+                );
+            }
+        }
+    }
+}
+
+// Runs until shutdown, each event is checked against the current config
+pub async fn run_notifier(shutdown: CancellationToken) {
+    let live = NOTIFIER_CONFIG
+        .get()
+        .expect("init_notifier_config must run before run_notifier");
+    let mut events = subscribe_appointment_events();
+    let client = reqwest::Client::new();
+    let mut dedup = Dedup::default();
+
+    loop {
+        let event = tokio::select! {
+            _ = shutdown.cancelled() => return,
+            event = events.recv() => event,
+        };
+        match event {
+            Ok(event) => notify(&client, &live.get(), &mut dedup, &event).await,
+            Err(RecvError::Lagged(skipped)) => print_n_log(
+                format!("Notifier fell behind, {} appointment event(s) skipped.", skipped),
+                true,
+                true,
+            ),
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+// Telegram puts the bot token in the path (/bot<id>:<secret>/sendMessage), it never
+// reaches the log. Other segments that merely start with "bot" are left alone
+fn redact_bot_token(path: &str) -> String {
+    let is_bot_token = |token: &str| match token.split_once(':') {
+        Some((id, secret)) => !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) && !secret.is_empty(),
+        None => false,
+    };
+    path.split('/')
+        .map(|segment| match segment.strip_prefix("bot") {
+            Some(token) if is_bot_token(token) => "bot[REDACTED]",
+            _ => segment,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Local stand-in for the webhook and Telegram endpoints, logs every request it gets
+// Point api_base or url at http://127.0.0.1:<port>, for email use a local SMTP sink
+// (e.g. MailHog on port 1025) with starttls = false
+pub async fn serve_notification_stand_in(port: u16, shutdown: CancellationToken) -> Result<(), String> {
+    async fn record(uri: Uri, body: Bytes) -> &'static str {
+        print_n_log(
+            format!("Stand-in received {}: {}", redact_bot_token(uri.path()), String::from_utf8_lossy(&body)),
+            true,
+            true,
+        );
+        r#"{"ok":true}"#
+    }
+
+    let app = Router::new().fallback(record);
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+        .await
+        .map_err(|e| format!(
+            "notifier.rs | serve_notification_stand_in(port: {}) | Error occured when binding the port. | {:?}",
+            port, e
+        ))?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { shutdown.cancelled().await })
+        .await
+        .map_err(|e| format!(
+            "notifier.rs | serve_notification_stand_in(port: {}) | Error occured in the server. | {:?}",
+            port, e
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::appointment_history::AppointmentObservation;
+    use super::super::consulate_registry::init_consulate_registry;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn observation(date: NaiveDate) -> AppointmentObservation {
+        AppointmentObservation {
+            consulate: serde_json::from_str(r#""tr-istanbul""#).unwrap(),
+            earliest_date: date,
+            observed_at: Utc::now(),
+            source_account: "account-0".to_string(),
+        }
+    }
+
+    fn config() -> NotifierConfig {
+        init_consulate_registry(&std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("consulates.json")).unwrap();
+        NotifierConfig {
+            dedup_window_secs: default_dedup_window_secs(),
+            applicants: vec![ApplicantNotifications {
+                applicant: "applicant-a".to_string(),
+                lang: default_lang(),
+                preferences: ApplicantPreferences {
+                    min_notice_days: 14,
+                    ..ApplicantPreferences::default()
+                },
+                channels: vec![ChannelConfig::Desktop],
+            }],
+        }
+    }
+
+    #[test]
+    fn a_date_that_slips_into_the_window_is_alerted() {
+        let config = config();
+        let today = ymd(2026, 3, 1);
+
+        // Too soon to make it, no alert
+        let too_soon = AppointmentEvent::NewEarliest { observation: observation(ymd(2026, 3, 5)), previous: None };
+        assert!(alerts_for(&config, &too_soon, today).is_empty());
+
+        // The slot was taken and the next one is far enough out
+        let slipped = AppointmentEvent::Slipped { observation: observation(ymd(2026, 3, 20)), previous: ymd(2026, 3, 5) };
+        let alerts = alerts_for(&config, &slipped, today);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].1.date, ymd(2026, 3, 20));
+        assert_eq!(alerts[0].1.previous, None);
+
+        // The same date again is deduplicated
+        let mut dedup = Dedup::default();
+        let window = Duration::from_secs(config.dedup_window_secs);
+        assert!(dedup.first_in_window(&alerts[0].1, window));
+        assert!(!dedup.first_in_window(&alerts_for(&config, &slipped, today)[0].1, window));
+    }
+
+    #[test]
+    fn disappearances_and_rejected_dates_are_not_alerted() {
+        let config = config();
+        let today = ymd(2026, 3, 1);
+        let slipped_too_soon = AppointmentEvent::Slipped { observation: observation(ymd(2026, 3, 6)), previous: ymd(2026, 3, 5) };
+        assert!(alerts_for(&config, &slipped_too_soon, today).is_empty());
+
+        let gone = AppointmentEvent::Disappeared {
+            consulate: observation(today).consulate,
+            last_date: ymd(2026, 3, 20),
+            observed_at: Utc::now(),
+            source_account: "account-0".to_string(),
+        };
+        assert!(alerts_for(&config, &gone, today).is_empty());
+    }
+
+    // A real failed send, the request URL carries the token and must not reach the error
+    #[tokio::test]
+    async fn send_errors_never_contain_the_bot_token() {
+        let token = "123456:ABC-secret_token";
+        let channel = ChannelConfig::Telegram {
+            // Nothing listens on port 9, the request fails at connect
+            api_base: "http://127.0.0.1:9".to_string(),
+            bot_token: Secret::new(token.to_string()),
+            chat_id: "42".to_string(),
+        };
+        let alert = Alert {
+            applicant: "applicant-a".to_string(),
+            consulate: observation(ymd(2026, 3, 20)).consulate,
+            consulate_name: "Istanbul".to_string(),
+            date: ymd(2026, 3, 20),
+            previous: None,
+            text: "Appointment available".to_string(),
+        };
+
+        let err = send(&reqwest::Client::new(), &channel, &alert).await.unwrap_err();
+        assert!(!err.contains(token), "{}", err);
+        assert!(!format!("{:?}", channel).contains(token));
+    }
+
+    #[test]
+    fn stand_in_log_never_contains_the_bot_token() {
+        let path = "/bot123456:ABC-secret_token/sendMessage";
+        let redacted = redact_bot_token(path);
+        assert_eq!(redacted, "/bot[REDACTED]/sendMessage");
+        assert!(!redacted.contains("ABC-secret_token"));
+        for untouched in ["/hooks/visa", "/bottles/42", "/bot/sendMessage", "/botabc:def/x", "/bot123/x"] {
+            assert_eq!(redact_bot_token(untouched), untouched);
+        }
+    }
+}
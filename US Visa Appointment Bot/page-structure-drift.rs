@@ -0,0 +1,139 @@
+// Page structure drift detection for the appointment parser
+// A redesign of the portal used to look exactly like "no appointments": missing
+// selectors returned an empty result and rows that failed to parse were skipped.
+// Every parse now produces a ParseReport with the selectors that matched nothing,
+// the rows that could not be parsed (with their raw text) and a fingerprint of the
+// page layout, and a missing appointment table raises an alert
+
+use chrono::NaiveDate;
+use scraper::Html;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use super::consulate_registry::ConsulateId;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct UnparsedRow {
+    // Cell texts joined with " | ", as they appeared on the page
+    pub raw_text: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ParseReport {
+    pub appointments: Vec<(ConsulateId, NaiveDate)>,
+    // City cells of posts listed without a free slot ("Randevu yok"), not drift
+    pub empty_posts: Vec<String>,
+    pub selector_misses: Vec<&'static str>,
+    pub unparsed_rows: Vec<UnparsedRow>,
+    pub fingerprint: String,
+}
+
+impl ParseReport {
+    pub fn new(fingerprint: String) -> ParseReport {
+        ParseReport {
+            appointments: Vec::new(),
+            empty_posts: Vec::new(),
+            selector_misses: Vec::new(),
+            unparsed_rows: Vec::new(),
+            fingerprint,
+        }
+    }
+
+    // The appointment table itself is gone, not just empty
+    pub fn structure_missing(&self) -> bool {
+        !self.selector_misses.is_empty()
+    }
+
+    pub fn unparsed(&mut self, raw_text: String, reason: String) {
+        self.unparsed_rows.push(UnparsedRow { raw_text, reason });
+    }
+
+    // One line summary for error messages and logs
+    pub fn summary(&self) -> String {
+        format!(
+            "parsed={} empty_posts={} selector_misses={:?} unparsed_rows={} fingerprint={}",
+            self.appointments.len(),
+            self.empty_posts.len(),
+            self.selector_misses,
+            self.unparsed_rows.len(),
+            self.fingerprint
+        )
+    }
+}
+
+// FNV-1a, stable across builds and platforms unlike std's DefaultHasher
+fn fnv1a(bytes: impl Iterator<Item = u8>, mut hash: u64) -> u64 {
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Hash of the set of "tag.class.class" signatures on the page
+// Text and repetition are ignored, so new dates or extra rows keep the fingerprint
+// and only a layout change (renamed classes, replaced elements) alters it
+pub fn schema_fingerprint(document: &Html) -> String {
+    let signatures: BTreeSet<String> = document
+        .root_element()
+        .descendants()
+        .filter_map(|node| node.value().as_element())
+        .map(|element| {
+            let mut classes: Vec<&str> = element.classes().collect();
+            classes.sort_unstable();
+            std::iter::once(element.name())
+                .chain(classes)
+                .collect::<Vec<_>>()
+                .join(".")
+        })
+        .collect();
+
+    let hash = signatures.iter().fold(0xcbf29ce484222325, |hash, signature| {
+        fnv1a(signature.bytes().chain(std::iter::once(b'\n')), hash)
+    });
+    format!("{:016x}", hash)
+}
+
+// Last fingerprint seen, so a changed layout is reported once and not on every request
+static LAST_FINGERPRINT: Mutex<Option<String>> = Mutex::new(None);
+
+// Logs unparsed rows, warns when the layout fingerprint changes and raises an
+// alert when the appointment table is missing
+pub fn report_drift(report: &ParseReport) {
+    for row in &report.unparsed_rows {
+        print_n_log(
+            format!("Unparsed appointment row: '{}' ({})", row.raw_text, row.reason),
+            true,
+            true,
+        );
+    }
+
+    let previous = LAST_FINGERPRINT
+        .lock()
+        .unwrap()
+        .replace(report.fingerprint.clone());
+    let changed = previous.as_ref().is_some_and(|p| *p != report.fingerprint);
+
+    if report.structure_missing() {
+        if previous.as_ref() != Some(&report.fingerprint) {
+            print_n_log(
+                format!(
+                    "ALERT: The appointment page structure changed, the appointment table is missing. | {}",
+                    report.summary()
+                ),
+                true,
+                true,
+            );
+        }
+    } else if changed {
+        print_n_log(
+            format!(
+                "The appointment page layout changed but the table was still found. | {}",
+                report.summary()
+            ),
+            true,
+            true,
+        );
+    }
+}
@@ -0,0 +1,186 @@
+// Snapshot checks for the appointment page parser
+// fixtures/appointments holds saved portal pages with personal data scrubbed, each
+// <name>.html next to <name>.snapshot.json with the ParseReport it must produce.
+// verify_fixtures runs them all offline, so a parser change can be checked without
+// a live session, and add_fixture turns a freshly saved page into a new fixture
+// when the portal changes
+
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use super::consulate_registry::init_consulate_registry;
+use super::specialized_html_parsing::parse_appointments;
+
+pub const FIXTURE_DIR: &str = "fixtures/appointments";
+const SNAPSHOT_EXTENSION: &str = "snapshot.json";
+const SCRUBBED: &str = "[SCRUBBED]";
+
+fn snapshot_path(html_path: &Path) -> PathBuf {
+    html_path.with_extension(SNAPSHOT_EXTENSION)
+}
+
+fn snapshot_of(html: &str) -> serde_json::Value {
+    serde_json::to_value(parse_appointments(html)).unwrap_or_default()
+}
+
+fn fixture_pages(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!(
+        "parser_snapshots.rs | fixture_pages(dir: {:?}) | Error occured when reading the fixture directory. | {:?}",
+        dir, e
+    ))?;
+    let mut pages: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+        .collect();
+    pages.sort();
+    Ok(pages)
+}
+
+// Parses every fixture and compares the report with its snapshot
+// Ok holds the number of fixtures checked, Err one line per mismatch
+pub fn verify_fixtures(dir: &Path) -> Result<usize, Vec<String>> {
+    let pages = fixture_pages(dir).map_err(|e| vec![e])?;
+    let mut failures = Vec::new();
+
+    for page in &pages {
+        let html = match std::fs::read_to_string(page) {
+            Ok(html) => html,
+            Err(e) => {
+                failures.push(format!("{:?}: cannot read the page | {:?}", page, e));
+                continue;
+            }
+        };
+        let expected: serde_json::Value = match std::fs::read_to_string(snapshot_path(page))
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|text| serde_json::from_str(&text).map_err(|e| format!("{:?}", e)))
+        {
+            Ok(expected) => expected,
+            Err(e) => {
+                failures.push(format!("{:?}: missing or invalid snapshot | {}", page, e));
+                continue;
+            }
+        };
+
+        let actual = snapshot_of(&html);
+        if actual != expected {
+            failures.push(format!(
+                "{:?}: snapshot mismatch\nexpected: {}\nactual:   {}",
+                page, expected, actual
+            ));
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(pages.len())
+    } else {
+        Err(failures)
+    }
+}
+
+// Removes what a saved page can carry about the applicant: emails, CSRF and
+// authenticity tokens, the numeric ids in schedule/group URLs, and any extra
+// strings given by the caller (names, passport numbers)
+pub fn scrub_page(html: &str, extra: &[String]) -> String {
+    let rules = [
+        (r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}", "applicant@example.com"),
+        (r#"(name="csrf-token"\s+content=")[^"]*"#, "${1}[SCRUBBED]"),
+        (r#"(name="authenticity_token"\s+value=")[^"]*"#, "${1}[SCRUBBED]"),
+        (r"/(schedule|groups|applicants)/\d+", "/${1}/00000000"),
+    ];
+
+    let mut scrubbed = html.to_string();
+    for (pattern, replacement) in rules {
+        let regex = Regex::new(pattern).unwrap();
+        scrubbed = regex.replace_all(&scrubbed, replacement).into_owned();
+    }
+    for value in extra.iter().filter(|v| !v.is_empty()) {
+        scrubbed = scrubbed.replace(value.as_str(), SCRUBBED);
+    }
+    scrubbed
+}
+
+// Scrubs a saved page into the fixture directory and records what the parser
+// currently makes of it, review the snapshot before committing it
+pub fn add_fixture(dir: &Path, name: &str, source: &Path, extra: &[String]) -> Result<PathBuf, String> {
+    let html = std::fs::read_to_string(source).map_err(|e| format!(
+        "parser_snapshots.rs | add_fixture(name: {}, source: {:?}) | Error occured when reading the page. | {:?}",
+        name, source, e
+    ))?;
+    let html = scrub_page(&html, extra);
+
+    let page = dir.join(format!("{}.html", name));
+    if page.exists() {
+        return Err(format!("{:?} already exists", page));
+    }
+    write_fixture(&page, &html)?;
+    Ok(page)
+}
+
+fn write_fixture(page: &Path, html: &str) -> Result<(), String> {
+    let snapshot = serde_json::to_string_pretty(&snapshot_of(html)).unwrap_or_default();
+    std::fs::write(page, html)
+        .and_then(|_| std::fs::write(snapshot_path(page), snapshot + "\n"))
+        .map_err(|e| format!(
+            "parser_snapshots.rs | write_fixture(page: {:?}) | Error occured when writing the fixture. | {:?}",
+            page, e
+        ))
+}
+
+// Rewrites every snapshot from the current parser, for intended output changes
+pub fn update_snapshots(dir: &Path) -> Result<usize, String> {
+    let pages = fixture_pages(dir)?;
+    for page in &pages {
+        let html = std::fs::read_to_string(page).map_err(|e| format!(
+            "parser_snapshots.rs | update_snapshots(dir: {:?}) | Error occured when reading {:?}. | {:?}",
+            dir, page, e
+        ))?;
+        write_fixture(page, &html)?;
+    }
+    Ok(pages.len())
+}
+
+pub fn run_fixture_cli(registry_path: &Path, args: &[String]) -> Result<String, String> {
+    let usage = "Usage: fixtures verify | add <name> <saved_page.html> [redact...] | update";
+    init_consulate_registry(registry_path)?;
+    let dir = Path::new(FIXTURE_DIR);
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+    match args.as_slice() {
+        ["verify"] => verify_fixtures(dir)
+            .map(|count| format!("{} fixture(s) match their snapshots", count))
+            .map_err(|failures| failures.join("\n")),
+        ["add", name, source, extra @ ..] => {
+            let extra: Vec<String> = extra.iter().map(|s| s.to_string()).collect();
+            add_fixture(dir, name, Path::new(source), &extra)
+                .map(|page| format!("Added {:?}, review its snapshot before committing", page))
+        }
+        ["update"] => update_snapshots(dir).map(|count| format!("Updated {} snapshot(s)", count)),
+        _ => Err(usage.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest_dir() -> &'static Path {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn every_fixture_matches_its_snapshot() {
+        init_consulate_registry(&manifest_dir().join("consulates.json")).unwrap();
+
+        let checked = verify_fixtures(&manifest_dir().join(FIXTURE_DIR))
+            .unwrap_or_else(|mismatches| panic!("snapshot mismatches:\n{}", mismatches.join("\n")));
+        assert!(checked > 0, "no fixtures found in {}", FIXTURE_DIR);
+    }
+
+    #[test]
+    fn scrub_page_removes_personal_data() {
+        let html = r#"<meta name="csrf-token" content="abc123"><a href="/schedule/48151623">applicant.name@mail.com Jane Roe</a>"#;
+        let scrubbed = scrub_page(html, &["Jane Roe".to_string()]);
+        for personal in ["abc123", "48151623", "applicant.name@mail.com", "Jane Roe"] {
+            assert!(!scrubbed.contains(personal), "{} survived scrubbing: {}", personal, scrubbed);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// Classification of portal responses before they are parsed
+// An expired _yatri_session cookie redirects to the sign in page, which used to
+// parse as a page without appointments. The status, final URL and page content
+// now decide what came back, so an expired session can be re-authenticated and
+// maintenance or error pages are reported as such
+
+use reqwest::StatusCode;
+use scraper::{Html, Selector};
+use super::locale_date_parsing::normalize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PageKind {
+    LoggedIn,
+    LoginRedirect,
+    Maintenance,
+    ErrorPage,
+}
+
+// Title words of the maintenance and error pages in the languages the portal is
+// served in, matched as whole words so "Hatay" or "Terrorism" are not error pages
+const MAINTENANCE_MARKERS: [&str; 6] = ["maintenance", "bakim", "mantenimiento", "manutencao", "wartung", "wartungsarbeiten"];
+const ERROR_MARKERS: [&str; 5] = ["error", "hata", "fehler", "erro", "erreur"];
+
+// Normalized words of the page title
+fn title_words(document: &Html) -> Vec<String> {
+    let title_selector = Selector::parse("title").unwrap();
+    let title = document
+        .select(&title_selector)
+        .next()
+        .map(|title| normalize(&title.text().collect::<String>()))
+        .unwrap_or_default();
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn has_marker(words: &[String], markers: &[&str]) -> bool {
+    words.iter().any(|word| markers.contains(&word.as_str()))
+}
+
+// The session is checked first, a login page served with any status means the cookie is gone
+pub fn classify_response(status: StatusCode, final_path: &str, html: &str) -> PageKind {
+    if status == StatusCode::UNAUTHORIZED || final_path.contains("/users/sign_in") {
+        return PageKind::LoginRedirect;
+    }
+
+    let document = Html::parse_document(html);
+    let login_selector = Selector::parse(r#"form#sign_in_form, input[name="user[email]"]"#).unwrap();
+    if document.select(&login_selector).next().is_some() {
+        return PageKind::LoginRedirect;
+    }
+
+    let words = title_words(&document);
+    if status == StatusCode::SERVICE_UNAVAILABLE || has_marker(&words, &MAINTENANCE_MARKERS) {
+        return PageKind::Maintenance;
+    }
+    if status.is_client_error() || status.is_server_error() || has_marker(&words, &ERROR_MARKERS) {
+        return PageKind::ErrorPage;
+    }
+
+    PageKind::LoggedIn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const SCHEDULE_PATH: &str = "/tr-tr/niv/schedule/00000000/appointment";
+    const SIGN_IN_PATH: &str = "/tr-tr/niv/users/sign_in";
+
+    fn fixture(name: &str) -> String {
+        std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+    }
+
+    #[test]
+    fn classifies_every_kind_of_page() {
+        let logged_in = fixture("appointments/tr-two-posts.html");
+        let sign_in = fixture("responses/sign-in.html");
+        let maintenance = fixture("responses/maintenance.html");
+        let error = fixture("responses/error.html");
+
+        let cases = [
+            (StatusCode::OK, SCHEDULE_PATH, &logged_in, PageKind::LoggedIn),
+            // An appointment page without any post is still a logged in page
+            (StatusCode::OK, SCHEDULE_PATH, &fixture("appointments/tr-no-appointments.html"), PageKind::LoggedIn),
+            // Redirected to the sign in page
+            (StatusCode::OK, SIGN_IN_PATH, &sign_in, PageKind::LoginRedirect),
+            // The sign in form served under the schedule URL
+            (StatusCode::OK, SCHEDULE_PATH, &sign_in, PageKind::LoginRedirect),
+            (StatusCode::UNAUTHORIZED, SCHEDULE_PATH, &error, PageKind::LoginRedirect),
+            (StatusCode::OK, SCHEDULE_PATH, &maintenance, PageKind::Maintenance),
+            (StatusCode::SERVICE_UNAVAILABLE, SCHEDULE_PATH, &logged_in, PageKind::Maintenance),
+            (StatusCode::OK, SCHEDULE_PATH, &error, PageKind::ErrorPage),
+            (StatusCode::INTERNAL_SERVER_ERROR, SCHEDULE_PATH, &logged_in, PageKind::ErrorPage),
+            (StatusCode::NOT_FOUND, SCHEDULE_PATH, &logged_in, PageKind::ErrorPage),
+        ];
+        for (status, path, html, expected) in cases {
+            assert_eq!(classify_response(status, path, html), expected, "{} {}", status, path);
+        }
+    }
+
+    #[test]
+    fn markers_only_match_whole_words() {
+        let logged_in = fixture("appointments/tr-two-posts.html");
+        for title in ["Randevu Planla - Hatay", "Terrorism Travel Advisory", "Errors and Omissions Policy"] {
+            let html = logged_in.replace("Randevu Planla", title);
+            assert_eq!(classify_response(StatusCode::OK, SCHEDULE_PATH, &html), PageKind::LoggedIn, "{}", title);
+        }
+        let html = logged_in.replace("Randevu Planla", "Wartungsarbeiten");
+        assert_eq!(classify_response(StatusCode::OK, SCHEDULE_PATH, &html), PageKind::Maintenance);
+    }
+}
@@ -0,0 +1,154 @@
+// Runtime configuration shared by both bots
+// Timing parameters used to be compile time constants, now they are loaded from a
+// TOML file with environment overrides, validated, and hot reloaded on SIGHUP or
+// when the file changes. A reload that fails validation keeps the previous config
+
+use serde::{de::DeserializeOwned, Deserialize};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio_util::sync::CancellationToken;
+
+const FILE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub trait Validate {
+    fn validate(&self) -> Result<(), String>;
+}
+
+// The current config, readers get a cheap Arc snapshot
+pub struct LiveConfig<T> {
+    current: RwLock<Arc<T>>,
+}
+
+impl<T> LiveConfig<T> {
+    pub fn new(config: T) -> LiveConfig<T> {
+        LiveConfig {
+            current: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<T> {
+        self.current.read().unwrap().clone()
+    }
+
+    fn replace(&self, config: T) {
+        *self.current.write().unwrap() = Arc::new(config);
+    }
+}
+
+// File first, then env vars such as VISA_BOT__COOLDOWN_PER_REQUEST_SECS=20 on top
+pub fn load_config<T: DeserializeOwned + Validate>(path: &PathBuf, env_prefix: &str) -> Result<T, String> {
+    let config: T = config::Config::builder()
+        .add_source(config::File::from(path.as_path()))
+        .add_source(config::Environment::with_prefix(env_prefix).separator("__"))
+        .build()
+        .and_then(|c| c.try_deserialize())
+        .map_err(|e| format!(
+            "runtime_config.rs | load_config(path: {:?}) | Error occured when loading the config. | {:?}",
+            path, e
+        ))?;
+
+    config.validate().map_err(|e| format!(
+        "runtime_config.rs | load_config(path: {:?}) | Invalid config. | {}",
+        path, e
+    ))?;
+    Ok(config)
+}
+
+fn modified_at(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(unix)]
+async fn hangup(signal: &mut Option<tokio::signal::unix::Signal>) {
+    match signal {
+        Some(signal) => {
+            signal.recv().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// Reloads on SIGHUP or when the file's mtime changes, until shutdown
+pub async fn watch_config<T>(
+    live: &'static LiveConfig<T>,
+    path: PathBuf,
+    env_prefix: &'static str,
+    shutdown: CancellationToken,
+) where
+    T: DeserializeOwned + Validate + Send + Sync + 'static,
+{
+    #[cfg(unix)]
+    let mut sighup =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+    let mut last_modified = modified_at(&path);
+
+    loop {
+        #[cfg(unix)]
+        let reason = tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = hangup(&mut sighup) => "SIGHUP",
+            _ = sleep(FILE_POLL_INTERVAL) => "file change",
+        };
+        #[cfg(not(unix))]
+        let reason = tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = sleep(FILE_POLL_INTERVAL) => "file change",
+        };
+
+        let modified = modified_at(&path);
+        if reason == "file change" && modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+
+        match load_config::<T>(&path, env_prefix) {
+            Ok(config) => {
+                live.replace(config);
+                print_n_log(format!("Config reloaded ({}).", reason), true, true);
+            }
+            Err(err) => print_n_log(format!("Config reload rejected, keeping the current one. | {}", err), true, true),
+        }
+    }
+}
+
+// Visa bot timings
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct VisaConfig {
+    pub cooldown_per_request_secs: u64,
+    pub cookie_update_cooldown_secs: u64,
+    pub accounts_per_batch: usize,
+    pub cycle_per_batch: usize,
+}
+
+impl Validate for VisaConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.cooldown_per_request_secs == 0 {
+            return Err("cooldown_per_request_secs must be at least 1".to_string());
+        }
+        if self.cookie_update_cooldown_secs == 0 {
+            return Err("cookie_update_cooldown_secs must be at least 1".to_string());
+        }
+        if self.accounts_per_batch == 0 {
+            return Err("accounts_per_batch must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub const VISA_CONFIG_ENV_PREFIX: &str = "VISA_BOT";
+static VISA_CONFIG: OnceLock<LiveConfig<VisaConfig>> = OnceLock::new();
+
+// Loads the visa bot config at startup, a bad file stops the bot before it starts
+pub fn init_visa_config(path: &PathBuf) -> Result<&'static LiveConfig<VisaConfig>, String> {
+    let config = load_config::<VisaConfig>(path, VISA_CONFIG_ENV_PREFIX)?;
+    Ok(VISA_CONFIG.get_or_init(|| LiveConfig::new(config)))
+}
+
+pub fn visa_config() -> Arc<VisaConfig> {
+    VISA_CONFIG
+        .get()
+        .expect("init_visa_config must run before the loops start")
+        .get()
+}
@@ -0,0 +1,144 @@
+// Wrapper for cookies, passwords, API keys and proxy credentials
+// Debug, Display and Serialize always write a placeholder, so a secret that ends up
+// in a format!() for an error, a log line or a serialized struct is redacted instead
+// of written in plaintext
+// Shared by both bots, the value is only reachable through expose(), and files that
+// must store it (the encrypted vault) opt in with serialize_exposed
+
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+const REDACTED: &str = "[REDACTED]";
+
+// Deserializing reads the plain value, configs and the vault hold real secrets
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub const fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    // Every call site that needs the real value has to ask for it explicitly
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+// #[serde(serialize_with = "serialize_exposed")] for a field that has to be written
+// out for real, only for data that is encrypted before it reaches the disk
+pub fn serialize_exposed<T: Serialize, S: Serializer>(secret: &Secret<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    secret.0.serialize(serializer)
+}
+
+pub fn serialize_exposed_opt<T: Serialize, S: Serializer>(
+    secret: &Option<Secret<T>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    secret.as_ref().map(|secret| &secret.0).serialize(serializer)
+}
+
+impl<T: AsRef<str>> Secret<T> {
+    // Removes the secret from text we do not control, e.g. an error returned by a
+    // library that echoes the request it failed on
+    pub fn scrub(&self, text: &str) -> String {
+        let value = self.0.as_ref();
+        if value.is_empty() {
+            return text.to_string();
+        }
+        text.replace(value, REDACTED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOKIE: &str = "_yatri_session=a1b2c3d4e5f6";
+    const PASSWORD: &str = "hunter2-correct-horse";
+    const API_KEY: &str = "sk_live_0123456789abcdef";
+
+    fn assert_no_secret(text: &str) {
+        for raw in [COOKIE, PASSWORD, API_KEY] {
+            assert!(!text.contains(raw), "secret leaked into: {}", text);
+        }
+    }
+
+    #[test]
+    fn debug_and_display_are_redacted() {
+        for secret in [Secret::new(COOKIE), Secret::new(PASSWORD), Secret::new(API_KEY)] {
+            assert_no_secret(&format!("{:?}", secret));
+            assert_no_secret(&format!("{}", secret));
+            assert_no_secret(&secret.to_string());
+        }
+    }
+
+    #[test]
+    fn nested_secrets_are_redacted() {
+        let api_key = Secret::new(API_KEY.to_string());
+        let account = (Secret::new(PASSWORD.to_string()), api_key.clone());
+        assert_no_secret(&format!("{:?} {:?}", account, api_key));
+        assert_no_secret(&Secret::new(PASSWORD.to_string()).scrub(&format!("login failed for password={}", PASSWORD)));
+        assert_no_secret(&api_key.scrub(&format!("GET /prices?key={}&items=1", API_KEY)));
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Stored {
+        logged: Secret<String>,
+        #[serde(serialize_with = "serialize_exposed")]
+        kept: Secret<String>,
+        #[serde(serialize_with = "serialize_exposed_opt")]
+        maybe_kept: Option<Secret<String>>,
+    }
+
+    #[test]
+    fn serialization_is_redacted_unless_exposed() {
+        let stored = Stored {
+            logged: Secret::new(COOKIE.to_string()),
+            kept: Secret::new(PASSWORD.to_string()),
+            maybe_kept: Some(Secret::new(API_KEY.to_string())),
+        };
+        let json = serde_json::to_string(&stored).unwrap();
+        assert!(!json.contains(COOKIE), "{}", json);
+        assert!(json.contains(REDACTED));
+        assert!(json.contains(PASSWORD) && json.contains(API_KEY), "{}", json);
+
+        // Reading back gets the real values of the exposed fields
+        let read: Stored = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.kept.expose(), PASSWORD);
+        assert_eq!(read.maybe_kept.unwrap().expose(), API_KEY);
+        assert_eq!(serde_json::to_string(&Secret::new(COOKIE)).unwrap(), format!("\"{}\"", REDACTED));
+    }
+
+    #[test]
+    fn scrub_keeps_text_without_the_secret() {
+        let password = Secret::new(PASSWORD.to_string());
+        assert_eq!(password.scrub("connection reset"), "connection reset");
+        assert_eq!(Secret::new(String::new()).scrub("unchanged"), "unchanged");
+    }
+}
@@ -1,91 +1,146 @@
 // Specialized HTML parsing for appointment data extraction
-// with localized date handling (Turkish dates)
+// with localized date handling (see locale-date-parsing.rs)
 
 use chrono::NaiveDate;
 use scraper::{Html, Selector};
-use std::collections::HashMap;
-use super::super::structs::KonsoloslukSehir;
+use super::super::secret::Secret;
+use super::consulate_registry::{consulate_registry, ConsulateId};
+use super::locale_date_parsing::{detect_locale, is_no_appointment_text, parse_localized_date};
+use super::response_classification::{classify_response, PageKind};
+use super::page_structure_drift::{report_drift, schema_fingerprint, ParseReport};
 
+// Why no dates came back, so the caller can tell a quiet page from a broken parser
+#[derive(Debug)]
+pub enum EarliestDateError {
+    // The request or reading the response failed
+    Request(String),
+    // The appointment table is there but no post on it could be parsed
+    NoAppointments(String),
+    // The appointment table could not be found, the portal layout changed
+    StructureChanged(String),
+    // The cookie is no longer valid, the portal sent the sign in page
+    SessionExpired(String),
+    // The portal is down for maintenance
+    Maintenance(String),
+    // The portal answered with an error page
+    ErrorPage(String),
+}
+
+impl std::fmt::Display for EarliestDateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EarliestDateError::Request(msg)
+            | EarliestDateError::NoAppointments(msg)
+            | EarliestDateError::StructureChanged(msg)
+            | EarliestDateError::SessionExpired(msg)
+            | EarliestDateError::Maintenance(msg)
+            | EarliestDateError::ErrorPage(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl From<EarliestDateError> for String {
+    fn from(err: EarliestDateError) -> String {
+        err.to_string()
+    }
+}
+
+// cookie_string and proxy are formatted as [REDACTED], library errors are scrubbed
+// as well since they can echo the request they failed on
+// Returns every post listed on the page in page order
 pub async fn get_earliest_date(
     user_payment_id: String,
-    cookie_string: String,
-    proxy: String,
-) -> Result<((KonsoloslukSehir, NaiveDate), (KonsoloslukSehir, NaiveDate)), String> {
+    cookie_string: Secret<String>,
+    proxy: Secret<String>,
+) -> Result<Vec<(ConsulateId, NaiveDate)>, EarliestDateError> {
     // Get the HTML response using the API helper
     let res = api::usvisainfo_api::get_earliest_date(
         user_payment_id.clone(), 
-        cookie_string.clone(), 
-        proxy.clone()
-    ).await.map_err(|e| format!(
-        "usvisainfo.rs | get_earliest_date(user_payment_id={}, cookie_string={}) | Error occured when sending the api request. | {:?}", 
-        user_payment_id.clone(), cookie_string.clone(), e
-    ))?;
+        cookie_string.expose().clone(), 
+        proxy.expose().clone()
+    ).await.map_err(|e| EarliestDateError::Request(format!(
+        "usvisainfo.rs | get_earliest_date(user_payment_id={}, cookie_string={}) | Error occured when sending the api request. | {}", 
+        user_payment_id.clone(), cookie_string, cookie_string.scrub(&format!("{:?}", e))
+    )))?;
+
+    // Redirects are followed, so the final URL shows a bounce to the sign in page
+    let status = res.status();
+    let final_path = res.url().path().to_string();
 
     // Extract the HTML text
-    let res_http = res.text().await.map_err(|e| format!(
-        "usvisainfo.rs | get_earliest_date(user_payment_id={}, cookie_string={}) | Error occured when getting the text from the api response. | {:?}", 
-        user_payment_id.clone(), cookie_string.clone(), e
-    ))?;
+    let res_http = res.text().await.map_err(|e| EarliestDateError::Request(format!(
+        "usvisainfo.rs | get_earliest_date(user_payment_id={}, cookie_string={}) | Error occured when getting the text from the api response. | {}", 
+        user_payment_id.clone(), cookie_string, cookie_string.scrub(&format!("{:?}", e))
+    )))?;
+
+    // Only a logged in page is parsed, anything else would read as "no appointments"
+    match classify_response(status, &final_path, &res_http) {
+        PageKind::LoggedIn => {}
+        PageKind::LoginRedirect => return Err(EarliestDateError::SessionExpired(format!(
+            "usvisainfo.rs | get_earliest_date(user_payment_id={}, cookie_string={}) | The session expired, the sign in page was returned. | status={} path={}", 
+            user_payment_id.clone(), cookie_string, status, final_path
+        ))),
+        PageKind::Maintenance => return Err(EarliestDateError::Maintenance(format!(
+            "usvisainfo.rs | get_earliest_date(user_payment_id={}, cookie_string={}) | The portal is under maintenance. | status={}", 
+            user_payment_id.clone(), cookie_string, status
+        ))),
+        PageKind::ErrorPage => return Err(EarliestDateError::ErrorPage(format!(
+            "usvisainfo.rs | get_earliest_date(user_payment_id={}, cookie_string={}) | The portal returned an error page. | status={} path={}", 
+            user_payment_id.clone(), cookie_string, status, final_path
+        ))),
+    }
 
     // Parse the HTML to extract appointment information
-    let appointments = parse_appointments(&res_http).map_err(|e| format!(
-        "usvisainfo.rs | get_earliest_date(user_payment_id={}, cookie_string={}) | Error occured when parsing the appointments. | {:?}", 
-        user_payment_id.clone(), cookie_string.clone(), e
-    ))?;
-
-    // Convert HashMap to Vec<(Konsolosluk, NaiveDate)>
-    let vec: Vec<_> = appointments.into_iter().collect();
-
-    // Convert Vec to tuple (ensuring we have exactly two appointments)
-    let tuple = match vec.as_slice() {
-        [(k1, v1), (k2, v2)] => ((k1.clone(), *v1), (k2.clone(), *v2)),
-        _ => return Err(format!(
-            "usvisainfo.rs | get_earliest_date(user_payment_id={}, cookie_string={}) | Error occured while more than 2 appointment dates were found. | vec={:?}", 
-            user_payment_id.clone(), cookie_string.clone(), vec
-        )),
-    };
+    let report = parse_appointments(&res_http);
+    report_drift(&report);
 
-    Ok(tuple)
+    let context = format!(
+        "usvisainfo.rs | get_earliest_date(user_payment_id={}, cookie_string={})",
+        user_payment_id, cookie_string
+    );
+    appointments_from_report(report, &context)
 }
 
-// Parse Turkish date strings into NaiveDate objects
-fn parse_turkish_date(date_str: &str) -> Result<NaiveDate, Box<dyn std::error::Error>> {
-    let parts: Vec<&str> = date_str.split_whitespace().collect();
-    if parts.len() != 3 {
-        return Err("Invalid date format".into());
+// Tells an empty page from a broken parse, `context` starts every error message
+fn appointments_from_report(
+    report: ParseReport,
+    context: &str,
+) -> Result<Vec<(ConsulateId, NaiveDate)>, EarliestDateError> {
+    if report.structure_missing() {
+        return Err(EarliestDateError::StructureChanged(format!(
+            "{} | The page structure changed, the appointment table was not found. | {}",
+            context, report.summary()
+        )));
     }
 
-    let day: u32 = parts[0].parse()?;
-    
-    // Map Turkish month names to their numerical values
-    let month = match parts[1].trim_end_matches(',') {
-        "Ocak" => 1,     // January
-        "Şubat" => 2,    // February
-        "Mart" => 3,     // March
-        "Nisan" => 4,    // April
-        "Mayıs" => 5,    // May
-        "Haziran" => 6,  // June
-        "Temmuz" => 7,   // July
-        "Ağustos" => 8,  // August
-        "Eylül" => 9,    // September
-        "Ekim" => 10,    // October
-        "Kasım" => 11,   // November
-        "Aralık" => 12,  // December
-        _ => return Err("Invalid month".into()),
-    };
-    
-    let year: i32 = parts[2].parse()?;
+    // Rows that were listed but did not parse are drift, not an empty page,
+    // otherwise every post would be reported as gone. Posts marked as having no
+    // appointment ("Randevu yok") are not in unparsed_rows
+    if report.appointments.is_empty() && !report.unparsed_rows.is_empty() {
+        return Err(EarliestDateError::StructureChanged(format!(
+            "{} | Appointment rows were listed but none could be parsed. | {}",
+            context, report.summary()
+        )));
+    }
 
-    // Create the date, ensuring it's valid
-    Ok(NaiveDate::from_ymd_opt(year, month, day).ok_or("Invalid date")?)
+    if report.appointments.is_empty() {
+        return Err(EarliestDateError::NoAppointments(format!(
+            "{} | No post on the page has an appointment date. | {}",
+            context, report.summary()
+        )));
+    }
+
+    Ok(report.appointments)
 }
 
-// Extract appointment information from HTML using CSS selectors
-fn parse_appointments(
-    html: &str,
-) -> Result<HashMap<KonsoloslukSehir, NaiveDate>, Box<dyn std::error::Error>> {
+// Posts keep the order of the table, a post listed twice keeps its earliest date
+// Selectors that match nothing and rows that fail to parse are recorded in the report
+pub fn parse_appointments(html: &str) -> ParseReport {
     // Parse the HTML document
     let document = Html::parse_document(html);
+    let mut report = ParseReport::new(schema_fingerprint(&document));
+    // Month names follow the language the page was served in
+    let locale = detect_locale(&document);
     
     // Define CSS selectors for the elements containing appointment data
     let div_selector = Selector::parse("div.medium-3.column").unwrap();
@@ -93,44 +148,176 @@ fn parse_appointments(
     let row_selector = Selector::parse("tr").unwrap();
     let cell_selector = Selector::parse("td").unwrap();
 
-    // Store city -> date mappings
-    let mut appointments = HashMap::new();
-
     // Find the div containing the appointment table
-    if let Some(div) = document.select(&div_selector).next() {
-        // Find the table within that div
-        if let Some(table) = div.select(&table_selector).next() {
-            // Process each row in the table
-            for row in table.select(&row_selector) {
-                let cells: Vec<_> = row.select(&cell_selector).collect();
-                if cells.len() == 2 {
-                    // Extract city and date from each row
-                    let city = cells[0].text().next().unwrap_or("").trim().to_string();
-                    let date_str = cells[1].text().next().unwrap_or("").trim();
-                    
-                    if !city.is_empty() && !date_str.is_empty() {
-                        // Parse the city name and date
-                        if let (Ok(date), Ok(city_enum)) =
-                            (parse_turkish_date(date_str), parse_city(&city))
-                        {
-                            appointments.insert(city_enum, date);
-                        }
-                    }
+    let div = match document.select(&div_selector).next() {
+        Some(div) => div,
+        None => {
+            report.selector_misses.push("div.medium-3.column");
+            return report;
+        }
+    };
+    // Find the table within that div
+    let table = match div.select(&table_selector).next() {
+        Some(table) => table,
+        None => {
+            report.selector_misses.push("table.for-layout");
+            return report;
+        }
+    };
+
+    // Process each row in the table
+    for row in table.select(&row_selector) {
+        let cells: Vec<_> = row.select(&cell_selector).collect();
+        let raw_text = || {
+            cells
+                .iter()
+                .map(|cell| cell.text().collect::<String>().trim().to_string())
+                .collect::<Vec<_>>()
+                .join(" | ")
+        };
+
+        // Header rows use th, they have no td at all
+        if cells.is_empty() {
+            continue;
+        }
+        if cells.len() != 2 {
+            report.unparsed(raw_text(), format!("expected 2 cells, found {}", cells.len()));
+            continue;
+        }
+
+        // Extract city and date from each row
+        let city = cells[0].text().next().unwrap_or("").trim().to_string();
+        let date_str = cells[1].text().next().unwrap_or("").trim();
+        if city.is_empty() || date_str.is_empty() {
+            report.unparsed(raw_text(), "empty city or date".to_string());
+            continue;
+        }
+        // A post that is listed but has no free slot right now
+        if is_no_appointment_text(date_str) {
+            report.empty_posts.push(city);
+            continue;
+        }
+
+        // Parse the city name and date
+        match (parse_localized_date(date_str, locale), parse_city(&city)) {
+            (Ok(date), Ok(consulate)) => {
+                match report.appointments.iter_mut().find(|(c, _)| *c == consulate) {
+                    Some((_, earliest)) => *earliest = (*earliest).min(date),
+                    None => report.appointments.push((consulate, date)),
                 }
             }
+            (Err(e), _) => report.unparsed(raw_text(), format!("{}", e)),
+            (_, Err(e)) => report.unparsed(raw_text(), e),
         }
     }
 
-    Ok(appointments)
+    report
 }
 
-// Convert city string to enum value
-fn parse_city(city_string: &str) -> Result<KonsoloslukSehir, String> {
-    match city_string {
-        "Istanbul" => return Ok(KonsoloslukSehir::Istanbul),
-        "Ankara" => return Ok(KonsoloslukSehir::Ankara),
-        _ => {}
+// Resolve a city cell to a post from the consulate registry
+fn parse_city(city_string: &str) -> Result<ConsulateId, String> {
+    consulate_registry()
+        .resolve(city_string)
+        .map(|consulate| consulate.id.clone())
+        .ok_or(format!("Given city string: {}", city_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+    use std::path::Path;
+    use super::super::appointment_history::{AppointmentEvent, AppointmentHistory};
+    use super::super::consulate_registry::init_consulate_registry;
+
+    fn fixture_report(name: &str) -> ParseReport {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        init_consulate_registry(&dir.join("consulates.json")).unwrap();
+        let html = std::fs::read_to_string(dir.join("fixtures/appointments").join(name)).unwrap();
+        parse_appointments(&html)
+    }
+
+    #[test]
+    fn posts_without_a_slot_are_not_drift() {
+        let report = fixture_report("tr-two-posts.html");
+        assert_eq!(report.empty_posts, vec!["Adana".to_string()]);
+        assert!(report.unparsed_rows.is_empty());
+        assert_eq!(appointments_from_report(report, "test").unwrap().len(), 2);
     }
 
-    Err(format!("Given city string: {}", city_string))
+    #[test]
+    fn a_page_where_every_post_is_empty_makes_them_disappear() {
+        let report = fixture_report("tr-no-appointments.html");
+        let err = appointments_from_report(report, "test").unwrap_err();
+        assert!(matches!(err, EarliestDateError::NoAppointments(_)), "{:?}", err);
+
+        // check_appointments records the empty check, both posts seen before are gone
+        let earlier = appointments_from_report(fixture_report("tr-two-posts.html"), "test").unwrap();
+        let mut history = AppointmentHistory::default();
+        history.record(&earlier, "account-0", Utc::now());
+        let events = history.record(&[], "account-0", Utc::now());
+
+        let gone: Vec<&ConsulateId> = events
+            .iter()
+            .filter_map(|event| match event {
+                AppointmentEvent::Disappeared { consulate, .. } => Some(consulate),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(gone.len(), 2, "{:?}", events);
+        for (consulate, _) in &earlier {
+            assert!(gone.contains(&consulate), "{} did not disappear", consulate);
+        }
+    }
+
+    #[test]
+    fn unknown_cell_text_is_still_drift() {
+        let html = include_str!("fixtures/appointments/tr-no-appointments.html").replace("Randevu yok", "Bakımda");
+        init_consulate_registry(&Path::new(env!("CARGO_MANIFEST_DIR")).join("consulates.json")).unwrap();
+        let err = appointments_from_report(parse_appointments(&html), "test").unwrap_err();
+        assert!(matches!(err, EarliestDateError::StructureChanged(_)), "{:?}", err);
+    }
+
+    proptest! {
+        // The page comes from the network, any cell text must give a consistent report
+        #[test]
+        fn any_table_gives_a_consistent_report(
+            rows in prop::collection::vec(("\\PC{0,20}", "\\PC{0,30}"), 0..8),
+        ) {
+            init_consulate_registry(&Path::new(env!("CARGO_MANIFEST_DIR")).join("consulates.json")).unwrap();
+            // Escaped so the generated text stays inside its cell
+            let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+            let cells: String = rows
+                .iter()
+                .map(|(city, date)| format!("<tr><td>{}</td><td>{}</td></tr>", escape(city), escape(date)))
+                .collect();
+            let html = format!(
+                r#"<html lang="tr"><body><div class="medium-3 column"><table class="for-layout">{}</table></div></body></html>"#,
+                cells
+            );
+            let report = parse_appointments(&html);
+
+            prop_assert!(!report.structure_missing());
+            prop_assert!(report.appointments.len() + report.empty_posts.len() + report.unparsed_rows.len() <= rows.len());
+            // Each post appears once, with its earliest date
+            let mut seen = HashSet::new();
+            for (consulate, _) in &report.appointments {
+                prop_assert!(seen.insert(consulate.clone()), "{} listed twice", consulate);
+            }
+            prop_assert_eq!(report.fingerprint.len(), 16);
+        }
+
+        #[test]
+        fn arbitrary_pages_never_panic(html in "\\PC{0,200}") {
+            init_consulate_registry(&Path::new(env!("CARGO_MANIFEST_DIR")).join("consulates.json")).unwrap();
+            let report = parse_appointments(&html);
+            // Nothing is parsed from a page whose table is missing
+            if report.structure_missing() {
+                prop_assert!(report.appointments.is_empty());
+                prop_assert!(report.unparsed_rows.is_empty());
+            }
+        }
+    }
 }
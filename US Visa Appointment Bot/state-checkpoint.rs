@@ -0,0 +1,215 @@
+// Checkpoint and restore of runtime state across restarts
+// State is written periodically to a local file with an atomic rename and read
+// back at startup, anything older than its max age is discarded instead of
+// restored. The helpers are shared, the visa bot state is defined below
+
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+const CHECKPOINT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct CheckpointFile<T> {
+    version: u32,
+    saved_at: DateTime<Utc>,
+    state: T,
+}
+
+// Temp file, fsync, rename, so a crash mid write leaves the previous checkpoint intact
+pub fn save_checkpoint<T: Serialize>(path: &Path, state: &T) -> Result<(), String> {
+    let file = CheckpointFile {
+        version: CHECKPOINT_VERSION,
+        saved_at: Utc::now(),
+        state,
+    };
+    let text = serde_json::to_vec(&file).map_err(|e| format!(
+        "state_checkpoint.rs | save_checkpoint(path: {:?}) | Error occured when serializing the state. | {:?}",
+        path, e
+    ))?;
+
+    let tmp_path = path.with_extension("tmp");
+    let write = || -> std::io::Result<()> {
+        let mut tmp = std::fs::File::create(&tmp_path)?;
+        tmp.write_all(&text)?;
+        tmp.sync_all()?;
+        std::fs::rename(&tmp_path, path)
+    };
+    write().map_err(|e| format!(
+        "state_checkpoint.rs | save_checkpoint(path: {:?}) | Error occured when writing the checkpoint. | {:?}",
+        path, e
+    ))
+}
+
+// None when there is no checkpoint yet or it was written by another version
+pub fn load_checkpoint<T: DeserializeOwned>(path: &Path) -> Result<Option<(DateTime<Utc>, T)>, String> {
+    let text = match std::fs::read(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(format!(
+                "state_checkpoint.rs | load_checkpoint(path: {:?}) | Error occured when reading the checkpoint. | {:?}",
+                path, e
+            ))
+        }
+    };
+
+    let file: CheckpointFile<T> = serde_json::from_slice(&text).map_err(|e| format!(
+        "state_checkpoint.rs | load_checkpoint(path: {:?}) | The checkpoint is corrupted. | {:?}",
+        path, e
+    ))?;
+    if file.version != CHECKPOINT_VERSION {
+        return Ok(None);
+    }
+    Ok(Some((file.saved_at, file.state)))
+}
+
+// How old a saved_at timestamp is, a clock that went backwards counts as fresh
+pub fn checkpoint_age(saved_at: DateTime<Utc>) -> Duration {
+    (Utc::now() - saved_at).to_std().unwrap_or(Duration::ZERO)
+}
+
+// Visa bot state
+
+// Sessions expire server side, older cookies are not worth restoring
+const COOKIE_MAX_AGE: Duration = Duration::from_secs(30 * 60);
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+// username -> when its current cookie was issued, a cookie restored at startup keeps
+// its original time so it is not mistaken for a fresh one on the next restart
+static COOKIE_OBTAINED_AT: LazyLock<Mutex<HashMap<String, DateTime<Utc>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Called wherever an account gets a new cookie
+pub fn mark_cookie_obtained(username: &str) {
+    COOKIE_OBTAINED_AT
+        .lock()
+        .unwrap()
+        .insert(username.to_string(), Utc::now());
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckpointedCookie {
+    cookie: String,
+    obtained_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VisaCheckpoint {
+    // username -> cookie and when it was issued, sealed with the vault key since cookies are credentials
+    sealed_cookies: String,
+    cookie_rotation: AccountRotation,
+    appointment_rotation: AccountRotation,
+}
+
+pub async fn checkpoint_visa_state(
+    path: &Path,
+    accounts: &Arc<RwLock<Vec<Account>>>,
+    vault: &Vault,
+) -> Result<(), String> {
+    let cookies: HashMap<String, CheckpointedCookie> = {
+        let accounts_guard = accounts.read().await;
+        let obtained_at = COOKIE_OBTAINED_AT.lock().unwrap();
+        accounts_guard
+            .iter()
+            .filter(|acc| !acc.cookie.expose().is_empty())
+            // A cookie without a known issue time cannot be aged, it is not saved
+            .filter_map(|acc| {
+                let obtained_at = *obtained_at.get(&acc.username)?;
+                Some((
+                    acc.username.clone(),
+                    CheckpointedCookie {
+                        cookie: acc.cookie.expose().clone(),
+                        obtained_at,
+                    },
+                ))
+            })
+            .collect()
+    };
+    let cookies_json = serde_json::to_vec(&cookies).unwrap_or_default();
+
+    let state = VisaCheckpoint {
+        sealed_cookies: vault.seal(&cookies_json)?,
+        cookie_rotation: *COOKIE_ROTATION.lock().unwrap(),
+        appointment_rotation: *APPOINTMENT_ROTATION.lock().unwrap(),
+    };
+    save_checkpoint(path, &state)
+}
+
+// Restores rotation positions always and each cookie only while it can still be valid,
+// judged by when that cookie was issued rather than when the checkpoint was written
+// Cookies are matched by username, so reordering accounts in the vault is harmless
+pub async fn restore_visa_state(
+    path: &Path,
+    accounts: &Arc<RwLock<Vec<Account>>>,
+    vault: &Vault,
+) -> Result<(), String> {
+    let state = match load_checkpoint::<VisaCheckpoint>(path)? {
+        Some((_, state)) => state,
+        None => return Ok(()),
+    };
+
+    // Positions past the end of a shorter account list start over on their own
+    *COOKIE_ROTATION.lock().unwrap() = state.cookie_rotation;
+    *APPOINTMENT_ROTATION.lock().unwrap() = state.appointment_rotation;
+
+    let cookies: HashMap<String, CheckpointedCookie> =
+        match serde_json::from_slice(&vault.unseal(&state.sealed_cookies)?) {
+            Ok(cookies) => cookies,
+            // Written before cookies carried their issue time, they cannot be aged
+            Err(_) => {
+                print_n_log(
+                    "Checkpointed cookies have no issue time, discarding them.".to_string(),
+                    true,
+                    true,
+                );
+                return Ok(());
+            }
+        };
+
+    let mut discarded = 0;
+    let mut accounts_guard = accounts.write().await;
+    let mut obtained_at = COOKIE_OBTAINED_AT.lock().unwrap();
+    for acc in accounts_guard.iter_mut() {
+        let Some(saved) = cookies.get(&acc.username) else {
+            continue;
+        };
+        if checkpoint_age(saved.obtained_at) > COOKIE_MAX_AGE {
+            discarded += 1;
+            continue;
+        }
+        acc.cookie = Secret::new(saved.cookie.clone());
+        obtained_at.insert(acc.username.clone(), saved.obtained_at);
+    }
+    if discarded > 0 {
+        print_n_log(
+            format!("{} checkpointed cookie(s) were older than {:?}, discarding them.", discarded, COOKIE_MAX_AGE),
+            true,
+            true,
+        );
+    }
+    Ok(())
+}
+
+// Periodic checkpoints, plus a final one when shutdown starts
+pub async fn run_visa_checkpointer(
+    path: PathBuf,
+    accounts: Arc<RwLock<Vec<Account>>>,
+    vault: Arc<Vault>,
+    shutdown: CancellationToken,
+) {
+    loop {
+        let keep_running = sleep_or_cancelled(&shutdown, CHECKPOINT_INTERVAL).await;
+
+        if let Err(err) = checkpoint_visa_state(&path, &accounts, &vault).await {
+            print_n_log(err, true, true);
+        }
+        if !keep_running {
+            break;
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// Supervisor for long running background tasks
+// update_cookies and check_appointments used to die silently on a panic while
+// the other kept going. Every supervised task runs in its own tokio task so a
+// panic is caught at the JoinHandle, then it is restarted with exponential
+// backoff. Restart counts are logged and kept for status reporting
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+// A task that ran this long before failing is considered healthy again
+const HEALTHY_RUN: Duration = Duration::from_secs(600);
+
+#[derive(Serialize, Clone, Default)]
+pub struct TaskStatus {
+    pub running: bool,
+    pub restarts: u32,
+    pub last_failure: Option<String>,
+    pub last_failure_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone)]
+pub struct TaskSupervisor {
+    statuses: Arc<Mutex<HashMap<&'static str, TaskStatus>>>,
+    shutdown: CancellationToken,
+}
+
+impl TaskSupervisor {
+    pub fn new(shutdown: CancellationToken) -> TaskSupervisor {
+        TaskSupervisor {
+            statuses: Arc::new(Mutex::new(HashMap::new())),
+            shutdown,
+        }
+    }
+
+    pub fn status(&self) -> HashMap<&'static str, TaskStatus> {
+        self.statuses.lock().unwrap().clone()
+    }
+
+    fn update(&self, name: &'static str, f: impl FnOnce(&mut TaskStatus)) {
+        f(self.statuses.lock().unwrap().entry(name).or_default());
+    }
+
+    // Runs the task built by `factory` until shutdown, restarting it whenever it
+    // panics or returns on its own. The loops never return unless cancelled, so an
+    // early return is treated as a failure too
+    pub async fn run<F, Fut>(self, name: &'static str, factory: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut backoff = INITIAL_BACKOFF;
+
+        loop {
+            self.update(name, |s| s.running = true);
+            let started = Instant::now();
+            let result = tokio::spawn(factory()).await;
+            self.update(name, |s| s.running = false);
+
+            if self.shutdown.is_cancelled() {
+                return;
+            }
+
+            let failure = match result {
+                Ok(()) => "The task returned unexpectedly.".to_string(),
+                Err(e) if e.is_panic() => format!("The task panicked: {}", panic_message(e.into_panic())),
+                Err(e) => format!("The task was cancelled: {:?}", e),
+            };
+
+            if started.elapsed() >= HEALTHY_RUN {
+                backoff = INITIAL_BACKOFF;
+            }
+
+            let mut restarts = 0;
+            self.update(name, |s| {
+                s.restarts += 1;
+                s.last_failure = Some(failure.clone());
+                s.last_failure_at = Some(Utc::now());
+                restarts = s.restarts;
+            });
+            print_n_log(
+                format!(
+                    "Task {} failed ({} restart(s) so far), restarting in {:?}. | {}",
+                    name, restarts, backoff, failure
+                ),
+                true,
+                true,
+            );
+
+            if !sleep_or_cancelled(&self.shutdown, backoff).await {
+                return;
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+// How the visa bot wires its loops up, each restart gets fresh clones of the shared state
+pub fn spawn_visa_tasks(
+    coordinator: &ShutdownCoordinator,
+    supervisor: &TaskSupervisor,
+    accounts: Arc<RwLock<Vec<Account>>>,
+) {
+    let token = coordinator.token();
+
+    let (cookie_accounts, cookie_token) = (accounts.clone(), token.clone());
+    coordinator.spawn(
+        "update_cookies",
+        supervisor.clone().run("update_cookies", move || {
+            update_cookies(cookie_accounts.clone(), cookie_token.clone())
+        }),
+    );
+
+    let (check_accounts, check_token) = (accounts, token.clone());
+    coordinator.spawn(
+        "check_appointments",
+        supervisor.clone().run("check_appointments", move || {
+            check_appointments(check_accounts.clone(), check_token.clone())
+        }),
+    );
+
+    // Needs init_notifier_config to have run
+    coordinator.spawn(
+        "notifier",
+        supervisor.clone().run("notifier", move || run_notifier(token.clone())),
+    );
+}